@@ -1,6 +1,10 @@
 use std:: sync::OnceLock;
 use std::fs::File;
 use std::io:: Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -99,12 +103,15 @@ mod oodle_lz {
 struct OodlePlatform {
     name: &'static str,
     bytes: &'static [u8],
+    /// SHA256 of the library, which extracted and user supplied copies are checked against
+    sha256: &'static str,
 }
 
 #[cfg(target_os = "linux")]
 static OODLE_PLATFORM: OodlePlatform = OodlePlatform {
     name: "liboo2corelinux64.so.9",
-    bytes: include_bytes!("../../liboo2corelinux64.so.9")
+    bytes: include_bytes!("../../liboo2corelinux64.so.9"),
+    sha256: "ed7e98f70be1254a80644efd3ae442ff61f854a2fe9debb0b978b95289884e9c",
 };
 
 
@@ -112,14 +119,43 @@ static OODLE_PLATFORM: OodlePlatform = OodlePlatform {
 static OODLE_PLATFORM: OodlePlatform = OodlePlatform {
     name: "oo2core_9_win64.dll",
     bytes: include_bytes!("../../oo2core_9_win64.dll"),
+    sha256: "6f5d41a7892ea6b2db420f2458dad2f84a63901c9a93ce9497337b16c195f457",
 };
 
+/// Environment variable holding the path of the Oodle library to load instead of extracting
+/// the embedded one
+pub const OODLE_PATH_ENV: &str = "REPAK_OODLE_PATH";
+
+static LIBRARY_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Loads the Oodle library from `path` instead of extracting the embedded one, taking
+/// precedence over [`OODLE_PATH_ENV`]. Only has an effect before the first call to [`oodle`].
+pub fn set_library_path(path: impl Into<PathBuf>) {
+    *LIBRARY_PATH.lock().unwrap() = Some(path.into());
+}
+
 
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("Oodle lib hash mismatch expected: {expected} got {found}")]
-    HashMismatch { expected: String, found: String },
+    #[error("Oodle lib {path:?} hash mismatch expected: {expected} got {found}")]
+    HashMismatch {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    #[error("failed to read Oodle lib {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to extract Oodle lib, tried {}", list_tried(.0))]
+    Extract(Vec<(PathBuf, Error)>),
+    #[error("failed to load Oodle lib {path:?}: {source}")]
+    Load {
+        path: PathBuf,
+        source: libloading::Error,
+    },
     #[error("Oodle compression failed")]
     CompressionFailed,
     #[error("Oodle initialization failed previously")]
@@ -131,15 +167,84 @@ pub enum Error {
 }
 
 
-fn fetch_oodle() -> Result<std::path::PathBuf> {
-    let oodle_path = std::env::current_exe()?.with_file_name(OODLE_PLATFORM.name);
-    if !oodle_path.exists() {
+fn list_tried(tried: &[(PathBuf, Error)]) -> String {
+    let tried: Vec<_> = tried
+        .iter()
+        .map(|(path, err)| format!("{path:?} ({err})"))
+        .collect();
+    tried.join(", ")
+}
+
+/// Finds the library to load: the path passed to [`set_library_path`], the path in
+/// [`OODLE_PATH_ENV`], or the embedded library extracted next to the current exe or to the cache
+/// directory if the former is not writable
+fn fetch_oodle() -> Result<PathBuf> {
+    let explicit = LIBRARY_PATH.lock().unwrap().clone();
+    if let Some(path) = explicit.or_else(|| std::env::var_os(OODLE_PATH_ENV).map(PathBuf::from)) {
+        verify(&path)?;
+        return Ok(path);
+    }
+
+    let mut candidates = vec![];
+    if let Ok(exe) = std::env::current_exe() {
+        candidates.push(exe.with_file_name(OODLE_PLATFORM.name));
+    }
+    if let Some(cache) = cache_dir() {
+        candidates.push(cache.join("repak").join(OODLE_PLATFORM.name));
+    }
+    extract(candidates)
+}
+
+/// Returns the first of `candidates` which holds the embedded library, extracting it if
+/// missing or damaged
+fn extract(candidates: Vec<PathBuf>) -> Result<PathBuf> {
+    let mut tried = vec![];
+    for path in candidates {
+        if verify(&path).is_ok() {
+            return Ok(path);
+        }
         // fuck downloading the lib virustotal smacks me for it
         // we finna embed the whole DLL into our program
         // and pull it out of our asses if we need it
-        File::create(&oodle_path)?.write_all(&OODLE_PLATFORM.bytes)?;
+        let written = (|| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            File::create(&path)?.write_all(OODLE_PLATFORM.bytes)
+        })();
+        match written.map_err(Error::Io).and_then(|()| verify(&path)) {
+            Ok(()) => return Ok(path),
+            Err(err) => tried.push((path, err)),
+        }
+    }
+    Err(Error::Extract(tried))
+}
+
+/// Checks the SHA256 of the library at `path`
+fn verify(path: &Path) -> Result<()> {
+    let bytes = std::fs::read(path).map_err(|source| Error::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let found = hex::encode(Sha256::digest(bytes));
+    if found != OODLE_PLATFORM.sha256 {
+        return Err(Error::HashMismatch {
+            path: path.to_owned(),
+            expected: OODLE_PLATFORM.sha256.to_owned(),
+            found,
+        });
+    }
+    Ok(())
+}
+
+/// `$XDG_CACHE_HOME` or `~/.cache`, `%LOCALAPPDATA%` on Windows
+fn cache_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else {
+        var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
     }
-    Ok(oodle_path)
 }
 
 pub struct Oodle {
@@ -214,7 +319,10 @@ static OODLE: OnceLock<Option<Oodle>> = OnceLock::new();
 fn load_oodle() -> Result<Oodle> {
     let path = fetch_oodle()?;
     unsafe {
-        let library = libloading::Library::new(path)?;
+        let library = libloading::Library::new(&path).map_err(|source| Error::Load {
+            path: path.clone(),
+            source,
+        })?;
         Ok(Oodle {
             compress: *library.get(b"OodleLZ_Compress")?,
             decompress: *library.get(b"OodleLZ_Decompress")?,
@@ -269,4 +377,33 @@ mod test {
 
         assert_eq!(data[..], uncomp[..]);
     }
+
+    #[test]
+    fn test_extract() {
+        let dir = std::env::temp_dir().join(format!("oodle_loader_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            hex::encode(Sha256::digest(OODLE_PLATFORM.bytes)),
+            OODLE_PLATFORM.sha256
+        );
+
+        // a damaged copy is reported and replaced
+        let path = dir.join("cache").join(OODLE_PLATFORM.name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"not a library").unwrap();
+        assert!(matches!(verify(&path), Err(Error::HashMismatch { path: p, .. }) if p == path));
+
+        // locations which cannot be written to are skipped
+        let blocked = dir.join("file");
+        std::fs::write(&blocked, b"").unwrap();
+        let unwritable = blocked.join(OODLE_PLATFORM.name);
+        assert_eq!(extract(vec![unwritable.clone(), path.clone()]).unwrap(), path);
+        verify(&path).unwrap();
+
+        let err = extract(vec![unwritable.clone()]).unwrap_err();
+        assert!(err.to_string().contains(&format!("{unwritable:?}")), "{err}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
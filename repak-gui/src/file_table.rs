@@ -60,15 +60,11 @@ impl FileTable {
                 read_utoc(&utoc_path, pak_reader,pak_path)
             }
             else {
-                let entries = pak_reader
-                    .files().to_vec();
-
-                entries
-                    .iter()
-                    .map(|entry| {
-                        let entry_pak = pak_reader.get_file_entry(entry).unwrap();
+                pak_reader
+                    .entries()
+                    .map(|(entry, entry_pak)| {
                         FileEntry {
-                            file_path: entry.clone(),
+                            file_path: entry.to_string(),
                             pak_path: PathBuf::from(pak_path),
                             pak_reader: pak_reader.clone(),
                             // entry: pak_reader.get_file_entry(entry).unwrap(),
@@ -2,7 +2,7 @@ pub mod install_mod_logic;
 
 use crate::install_mod::install_mod_logic::archives::*;
 use crate::install_mod::install_mod_logic::pak_files::create_repak_from_pak;
-use crate::utils::{collect_files, get_current_pak_characteristics};
+use crate::utils::{collect_files, get_current_pak_characteristics, get_pak_characteristics};
 use crate::utoc_utils::read_utoc;
 use crate::{setup_custom_style, ICON};
 use eframe::egui;
@@ -12,8 +12,7 @@ use egui_flex::{item, Flex, FlexAlign};
 use install_mod_logic::install_mods_in_viewport;
 use log::{debug, error};
 use repak::utils::AesKey;
-use repak::Compression::Oodle;
-use repak::{Compression, PakReader};
+use repak::{Compression, GameProfile, PakReader};
 use serde::de::Unexpected::Str;
 use std::fs::File;
 use std::io::BufReader;
@@ -348,12 +347,14 @@ impl ModInstallRequest {
 
 
 fn find_mods_from_archive(path: &str) -> Vec<InstallableMod> {
+    let profile = GameProfile::stellar_blade();
     let mut new_mods = Vec::<InstallableMod>::new();
     for entry in WalkDir::new(path) {
         let entry = entry.expect("Failed to read directory entry");
         let path = entry.path();
         if path.is_file() {
             let builder = repak::PakBuilder::new()
+                .profile(&profile)
                 .reader(&mut BufReader::new(File::open(path).unwrap()));
 
             if let Ok(builder) = builder {
@@ -382,9 +383,8 @@ fn find_mods_from_archive(path: &str) -> Vec<InstallableMod> {
                 }
                 // IF ONLY PAK IS FOUND WE NEED TO EXTRACT AND INSTALL THE PAK
                 else if pak_path.exists()  {
-                    let files = builder.files();
-                    len = files.len();
-                    modtype = get_current_pak_characteristics(files);
+                    len = builder.entries().count();
+                    modtype = get_pak_characteristics(&builder);
                 }
 
                 let installable_mod = InstallableMod {
@@ -394,13 +394,13 @@ fn find_mods_from_archive(path: &str) -> Vec<InstallableMod> {
                     is_dir: false,
                     reader: Some(builder),
                     mod_path: path.to_path_buf(),
-                    mount_point: "../../../".to_string(),
-                    path_hash_seed: "00000000".to_string(),
+                    mount_point: profile.mount_point.clone(),
+                    path_hash_seed: format!("{:08}", profile.path_hash_seed.unwrap_or_default()),
                     total_files: len,
                     iostore,
                     is_archived: false,
                     editing: false,
-                    compression: Oodle,
+                    compression: profile.compression[0].clone(),
                     ..Default::default()
                 };
 
@@ -413,6 +413,7 @@ fn find_mods_from_archive(path: &str) -> Vec<InstallableMod> {
 }
 
 fn map_to_mods_internal(paths: &[PathBuf]) -> Vec<InstallableMod> {
+    let profile = GameProfile::stellar_blade();
     let mut extensible_vec: Vec<InstallableMod> = Vec::new();
     let mut installable_mods = paths
         .iter()
@@ -427,12 +428,13 @@ fn map_to_mods_internal(paths: &[PathBuf]) -> Vec<InstallableMod> {
 
             if !is_dir && !is_archive {
                 let builder = repak::PakBuilder::new()
+                    .profile(&profile)
                     .reader(&mut BufReader::new(File::open(path.clone()).unwrap()));
                 match builder {
                     Ok(builder) => {
                         pak = Some(builder.clone());
-                        modtype = get_current_pak_characteristics(builder.files());
-                        len = builder.files().len();
+                        modtype = get_pak_characteristics(&builder);
+                        len = builder.entries().count();
                     }
                     Err(e) => {
                         error!("Error reading pak file: {}", e);
@@ -481,8 +483,8 @@ fn map_to_mods_internal(paths: &[PathBuf]) -> Vec<InstallableMod> {
                 is_dir,
                 reader: pak,
                 mod_path: path.clone(),
-                mount_point: "../../../".to_string(),
-                path_hash_seed: "00000000".to_string(),
+                mount_point: profile.mount_point.clone(),
+                path_hash_seed: format!("{:08}", profile.path_hash_seed.unwrap_or_default()),
                 total_files: len,
                 is_archived: is_archive,
                 ..Default::default()
@@ -3,12 +3,11 @@ use crate::install_mod::{InstallableMod};
 use crate::utils::collect_files;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use repak::Version;
 use std::io::BufWriter;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::atomic::AtomicI32;
-use retoc::*;
+use retoc::{action_to_zen, ActionToZen, Config, EngineVersion};
 use std::sync::Arc;
 use log::debug;
 use std::fs::File;
@@ -69,12 +68,14 @@ pub fn convert_to_iostore_directory(
         })
         .collect::<Vec<_>>();
 
+    let profile = repak::GameProfile::stellar_blade();
     let builder = repak::PakBuilder::new()
-        .compression(vec![pak.compression]);
+        .profile(&profile)
+        .compression(vec![pak.compression.clone()]);
 
     let mut pak_writer = builder.writer(
         BufWriter::new(output_file),
-        Version::V11,
+        profile.version,
         pak.mount_point.clone(),
         Some(pak.path_hash_seed.parse().unwrap()),
     );
@@ -5,7 +5,6 @@ use path_clean::PathClean;
 use path_slash::PathExt;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use repak::Version;
 use std::fs;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
@@ -113,12 +112,14 @@ pub fn repak_dir(
 
     paths.sort();
 
+    let profile = repak::GameProfile::stellar_blade();
     let builder = repak::PakBuilder::new()
-        .compression(vec![pak.compression]);
+        .profile(&profile)
+        .compression(vec![pak.compression.clone()]);
 
     let mut pak_writer = builder.writer(
         BufWriter::new(output_file),
-        Version::V11,
+        profile.version,
         pak.mount_point.clone(),
         Some(pak.path_hash_seed.parse().unwrap()),
     );
@@ -133,9 +134,12 @@ pub fn repak_dir(
                 .to_slash()
                 .expect("failed to convert to slash path");
 
-            let entry = entry_builder
+            let mut entry = entry_builder
                 .build_entry(true, std::fs::read(p).expect("WTF"), rel)
                 .expect("Failed to build entry");
+            if let Ok(modified) = std::fs::metadata(p).and_then(|m| m.modified()) {
+                entry = entry.with_timestamp(repak::utils::ticks(modified));
+            }
             (rel.to_string(), entry)
         })
         .collect::<Vec<_>>();
@@ -13,7 +13,7 @@ use crate::install_mod::{
     map_dropped_file_to_mods, map_paths_to_mods, InstallableMod, ModInstallRequest
 };
 use crate::utils::find_marvel_rivals;
-use crate::utils::get_current_pak_characteristics;
+use crate::utils::{get_current_pak_characteristics, get_pak_characteristics};
 use crate::utoc_utils::read_utoc;
 use eframe::egui::{
     self, style::Selection, Align, Align2, Button, Color32, IconData, Id, Label, LayerId, Order,
@@ -64,6 +64,9 @@ struct RepakModManager {
     #[serde(skip)]
     hide_welcome: bool,
     version: Option<String>,
+    /// AES key of encrypted IoStore mods in hex or base64
+    #[serde(default)]
+    aes_key: String,
 }
 
 #[derive(Clone)]
@@ -164,7 +167,8 @@ impl RepakModManager {
                     }
                 }
 
-                let builder = repak::PakBuilder::new();
+                let builder =
+                    repak::PakBuilder::new().profile(&repak::GameProfile::stellar_blade());
                 let pak = builder.reader(&mut BufReader::new(File::open(path).unwrap()));
 
                 if let Err(_e) = pak {
@@ -225,8 +229,6 @@ impl RepakModManager {
             .path
             .clone();
 
-        let full_paths = pak.files().into_iter().collect::<Vec<_>>();
-
         ui.collapsing("Encryption details", |ui| {
             ui.horizontal(|ui| {
                 ui.add(Label::new(RichText::new("Encryption: ").strong()));
@@ -264,19 +266,19 @@ impl RepakModManager {
             let mut utoc_path = pak_path.to_path_buf();
             utoc_path.set_extension("utoc");
 
-            let paths = {
+            let characteristics = {
                 if utoc_path.exists() {
                     let file = read_utoc(&utoc_path, pak, &pak_path)
                         .iter()
                         .map(|entry| entry.file_path.clone())
                         .collect::<Vec<_>>();
-                    file
+                    get_current_pak_characteristics(file)
                 } else {
-                    full_paths.clone()
+                    get_pak_characteristics(pak)
                 }
             };
 
-            ui.add(Label::new(get_current_pak_characteristics(paths)));
+            ui.add(Label::new(characteristics));
         });
         if self.table.is_none() {
             self.table = Some(FileTable::new(pak, &pak_path));
@@ -425,6 +427,7 @@ impl RepakModManager {
             info!("Loading config: {}", path.to_string_lossy());
             let data = fs::read_to_string(path)?;
             let mut config: Self = serde_json::from_str(&data)?;
+            utoc_utils::set_aes_key(&config.aes_key);
 
             debug!("Setting custom style");
             setup_custom_style(&ctx.egui_ctx);
@@ -690,6 +693,19 @@ impl RepakModManager {
                     }
                 });
             });
+        Flex::horizontal()
+            .w_full()
+            .align_items(FlexAlign::Center)
+            .show(ui, |flex_ui| {
+                flex_ui.add(item(), Label::new("AES key:"));
+                let key = flex_ui.add(
+                    item().grow(1.0),
+                    TextEdit::singleline(&mut self.aes_key).hint_text("hex or base64"),
+                );
+                if key.changed() {
+                    utoc_utils::set_aes_key(&self.aes_key);
+                }
+            });
     }
 }
 impl eframe::App for RepakModManager {
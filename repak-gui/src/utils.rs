@@ -100,45 +100,116 @@ pub fn get_current_pak_characteristics(mod_contents: Vec<String>) -> String {
     let mut fallback: Option<String> = None;
 
     for file in &mod_contents {
-        let path = file
-            .strip_prefix("SB/Content/SB/")
-            .or_else(|| file.strip_prefix("/Game/"))
-            .unwrap_or(file);
-
-        let category = path.split('/').next().unwrap_or_default();
-
-        match category {
-            "L10N" => {
-                // Extract character and language from the path
-                if let Some(character) = extract_character_from_path_audio(path) {
-                    if let Some(language) = extract_language_from_path(path) {
-                        return format!("Audio ({} - {})", character, language);
-                    }
-                }
-                // Fallback logic in case the extraction fails
-                return "Audio (Unknown)".to_string();
+        if let Some(characteristics) = path_characteristics(file, &mut fallback) {
+            return characteristics;
+        }
+    }
+
+    fallback.unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// Same as [`get_current_pak_characteristics`], but decides the `UI` and `Movies` categories by
+/// their directory instead of visiting every path below it. Paths are still classified in index
+/// order so the first decisive path wins as before.
+pub fn get_pak_characteristics(pak: &PakReader) -> String {
+    const ROOT: &str = "SB/Content/SB/";
+    let mut fallback: Option<String> = None;
+
+    let mut children = pak.list_dir(ROOT);
+    if children.is_empty() {
+        // entries rooted elsewhere, e.g. /Game/ paths, are classified one by one
+        for (path, _) in pak.entries() {
+            if let Some(characteristics) = path_characteristics(path, &mut fallback) {
+                return characteristics;
             }
-            "Art" => if let Some(character) = extract_character_from_path(path) {
-                match get_character_mod_skin(character.as_str()) {
-                    Some(ModType::Custom(skin)) => return skin,
-                    Some(ModType::Default(name)) => fallback = Some(name),
-                    None => {
-                        debug!("No character data found. Trying next");
-                    }
+        }
+        return fallback.unwrap_or_else(|| "Unknown".to_string());
+    }
+    children.sort_by_key(|child| match child {
+        DirChild::Directory(path) | DirChild::File(path, _) => *path,
+    });
+
+    let before = pak.entries().take_while(|(path, _)| *path < ROOT);
+    for (path, _) in before {
+        if let Some(characteristics) = path_characteristics(path, &mut fallback) {
+            return characteristics;
+        }
+    }
+    for child in children {
+        let dir = match child {
+            DirChild::Directory(dir) => dir,
+            DirChild::File(path, _) => {
+                if let Some(characteristics) = path_characteristics(path, &mut fallback) {
+                    return characteristics;
                 }
-            },
+                continue;
+            }
+        };
+        match dir.trim_end_matches('/').rsplit('/').next().unwrap_or_default() {
             "UI" => return "UI".to_string(),
             "Movies" => return "Movies".to_string(),
-            _ if path.contains("WwiseAudio") => return "Audio".to_string(),
-            _ => {}
+            _ => {
+                for (path, _) in pak.walk(dir) {
+                    if let Some(characteristics) = path_characteristics(path, &mut fallback) {
+                        return characteristics;
+                    }
+                }
+            }
+        }
+    }
+    let after = pak
+        .entries()
+        .skip_while(|(path, _)| *path < ROOT || path.starts_with(ROOT));
+    for (path, _) in after {
+        if let Some(characteristics) = path_characteristics(path, &mut fallback) {
+            return characteristics;
         }
     }
 
     fallback.unwrap_or_else(|| "Unknown".to_string())
 }
 
+/// Mod type decided by a single path. Default skins only set `fallback` since a later path may
+/// belong to a custom skin.
+fn path_characteristics(file: &str, fallback: &mut Option<String>) -> Option<String> {
+    let path = file
+        .strip_prefix("SB/Content/SB/")
+        .or_else(|| file.strip_prefix("/Game/"))
+        .unwrap_or(file);
+
+    let category = path.split('/').next().unwrap_or_default();
+
+    match category {
+        "L10N" => {
+            // Extract character and language from the path
+            if let Some(character) = extract_character_from_path_audio(path) {
+                if let Some(language) = extract_language_from_path(path) {
+                    return Some(format!("Audio ({} - {})", character, language));
+                }
+            }
+            // Fallback logic in case the extraction fails
+            return Some("Audio (Unknown)".to_string());
+        }
+        "Art" => if let Some(character) = extract_character_from_path(path) {
+            match get_character_mod_skin(character.as_str()) {
+                Some(ModType::Custom(skin)) => return Some(skin),
+                Some(ModType::Default(name)) => *fallback = Some(name),
+                None => {
+                    debug!("No character data found. Trying next");
+                }
+            }
+        },
+        "UI" => return Some("UI".to_string()),
+        "Movies" => return Some("Movies".to_string()),
+        _ if path.contains("WwiseAudio") => return Some("Audio".to_string()),
+        _ => {}
+    }
+    None
+}
+
 use log::{debug, info};
 use regex_lite::Regex;
+use repak::{DirChild, PakReader};
 use serde::{Deserialize, Serialize};
 
 pub fn find_marvel_rivals() -> Option<PathBuf> {
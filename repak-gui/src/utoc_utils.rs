@@ -1,38 +1,58 @@
+use std::fs::File;
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::RwLock;
+use log::{error, warn};
+use repak::utils::AesKey;
 use repak::PakReader;
-use retoc::{action_manifest, ActionManifest, Config, FGuid};
 
-pub fn read_utoc(utoc_path: &Path, pak_reader: &PakReader, pak_path: &Path) -> Vec<crate::file_table::FileEntry> {
-    let action_mn = ActionManifest::new(PathBuf::from(utoc_path));
-    let mut config = Config {
-        container_header_version_override: None,
-        ..Default::default()
-    };
+/// AES key for encrypted IoStore containers, from the "AES key" setting
+static AES_KEY: RwLock<Option<AesKey>> = RwLock::new(None);
 
-    let aes_toc =
-        retoc::AesKey::from_str("0C263D8C22DCB085894899C3A3796383E9BF9DE0CBFB08C9BF2DEF2E84F29D74")
-            .unwrap();
+/// Sets the key used by [`read_utoc`] from its hex or base64 form, clearing it if `key` is empty
+pub fn set_aes_key(key: &str) {
+    let key = match key.trim() {
+        "" => None,
+        key => AesKey::from_str(key)
+            .inspect_err(|_| warn!("Ignoring invalid AES key"))
+            .ok(),
+    };
+    *AES_KEY.write().unwrap() = key;
+}
 
-    config.aes_keys.insert(FGuid::default(), aes_toc.clone());
-    let config = Arc::new(config);
+pub fn read_utoc(utoc_path: &Path, pak_reader: &PakReader, pak_path: &Path) -> Vec<crate::file_table::FileEntry> {
+    let toc = File::open(utoc_path)
+        .map_err(repak::Error::from)
+        .and_then(|file| {
+            let mut builder = repak::PakBuilder::new().encryption(repak::UnrealStandard);
+            if let Some(key) = AES_KEY.read().unwrap().clone() {
+                builder = builder.key(key.0);
+            }
+            builder.iostore_reader(&mut BufReader::new(file))
+        });
+    let toc = match toc {
+        Ok(toc) => toc,
+        Err(e) => {
+            error!("Failed to read utoc {}: {}", utoc_path.display(), e);
+            return vec![];
+        }
+    };
 
-    let ops = action_manifest(action_mn,config).expect("Failed to read utoc");
-    let ret = ops.oplog.entries.iter().map(|entry| {
-        let name = entry.packagestoreentry.packagename.clone();
+    // directory index paths are relative to the mount point while pak paths include it
+    let mount_point = toc.mount_point().unwrap_or_default().trim_start_matches("../");
+    toc.files().map(|(path, chunk)| {
+        let stored = toc.chunk_blocks(chunk).iter().map(|b| b.compressed as u64).sum::<u64>();
+        let is_bulk = path.ends_with(".ubulk") || path.ends_with(".uptnl");
         crate::file_table::FileEntry {
-            file_path: name,
+            file_path: format!("{mount_point}{path}"),
             pak_path: PathBuf::from(pak_path),
             pak_reader: pak_reader.clone(),
-            // entry: pak_reader.get_file_entry(entry).unwrap(),
-            compressed: "Unavailable".to_string(),
-            uncompressed: "Unavailable".to_string(),
-            offset: "Unavailable".to_string(),
-            bulkdata: Some(entry.bulkdata.len()),
-            package_data: Some(entry.packagedata.len()),
+            compressed: stored.to_string(),
+            uncompressed: chunk.length.to_string(),
+            offset: format!("{:#x}", chunk.offset),
+            bulkdata: is_bulk.then_some(chunk.length as usize),
+            package_data: (!is_bulk).then_some(chunk.length as usize),
         }
-    }).collect::<Vec<_>>();
-
-    ret
-}
\ No newline at end of file
+    }).collect::<Vec<_>>()
+}
@@ -0,0 +1,220 @@
+//! Compression codecs, looked up by the FName stored in the pak footer.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{Compression, Error};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A compression method which can be registered with [`crate::PakBuilder::codec`]
+pub trait Codec: Send + Sync {
+    /// FName of the compression method as stored in the pak footer, e.g. `Oodle`
+    fn name(&self) -> &str;
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>>;
+    /// Decompresses a single compression block into `output`, which has the exact
+    /// uncompressed size of the block
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()>;
+}
+
+/// Registry of codecs by case-insensitive name, initially holding the built-in codecs
+#[derive(Clone)]
+pub struct Codecs(Vec<Arc<dyn Codec>>);
+
+impl Default for Codecs {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut codecs = Self(vec![]);
+        #[cfg(feature = "compression")]
+        {
+            codecs.register(Zlib);
+            codecs.register(Gzip);
+            codecs.register(Oodle);
+            codecs.register(Zstd);
+            codecs.register(Lz4);
+        }
+        codecs
+    }
+}
+
+impl std::fmt::Debug for Codecs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|codec| codec.name()))
+            .finish()
+    }
+}
+
+impl Codecs {
+    /// Registers `codec`, replacing any codec of the same name
+    pub fn register(&mut self, codec: impl Codec + 'static) {
+        self.0
+            .retain(|c| !c.name().eq_ignore_ascii_case(codec.name()));
+        self.0.push(Arc::new(codec));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Codec> {
+        self.0
+            .iter()
+            .find(|codec| codec.name().eq_ignore_ascii_case(name))
+            .map(|codec| codec.as_ref())
+    }
+
+    /// Names of the registered codecs
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|codec| codec.name())
+    }
+
+    /// Maps a compression name from a footer to a built-in method, a registered one or
+    /// [`Compression::Unknown`], ignoring case. Empty names denote unused slots.
+    pub(crate) fn resolve(&self, name: &str) -> Option<Compression> {
+        if name.is_empty() {
+            return None;
+        }
+        let builtin = <Compression as strum::VariantNames>::VARIANTS
+            .iter()
+            .find(|variant| variant.eq_ignore_ascii_case(name))
+            .and_then(|variant| Compression::from_str(variant).ok());
+        Some(match builtin {
+            Some(compression) => compression,
+            None => match self.get(name) {
+                Some(codec) => Compression::Custom(codec.name().to_owned()),
+                None => Compression::Unknown(name.to_owned()),
+            },
+        })
+    }
+
+    /// Codec implementing `compression`
+    pub(crate) fn codec(&self, compression: &Compression) -> Result<&dyn Codec> {
+        self.get(&compression.to_string())
+            .ok_or_else(|| match compression {
+                Compression::Custom(name) => Error::MissingCodec(name.clone()),
+                Compression::Unknown(name) => Error::UnknownCompression(name.clone()),
+                _ => Error::Compression,
+            })
+    }
+}
+
+#[cfg(feature = "compression")]
+fn read_exact<R: std::io::Read>(
+    mut reader: R,
+    output: &mut [u8],
+    compression: Compression,
+) -> Result<()> {
+    reader
+        .read_exact(output)
+        .map_err(|_| Error::DecompressionFailed(compression))
+}
+
+#[cfg(feature = "compression")]
+pub struct Zlib;
+#[cfg(feature = "compression")]
+impl Codec for Zlib {
+    fn name(&self) -> &str {
+        "Zlib"
+    }
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        use std::io::Write;
+        let mut compress =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        compress.write_all(data)?;
+        Ok(compress.finish()?)
+    }
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()> {
+        read_exact(
+            flate2::read::ZlibDecoder::new(data),
+            output,
+            Compression::Zlib,
+        )
+    }
+}
+
+#[cfg(feature = "compression")]
+pub struct Gzip;
+#[cfg(feature = "compression")]
+impl Codec for Gzip {
+    fn name(&self) -> &str {
+        "Gzip"
+    }
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        use std::io::Write;
+        let mut compress = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        compress.write_all(data)?;
+        Ok(compress.finish()?)
+    }
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()> {
+        read_exact(
+            flate2::read::GzDecoder::new(data),
+            output,
+            Compression::Gzip,
+        )
+    }
+}
+
+#[cfg(feature = "compression")]
+pub struct Zstd;
+#[cfg(feature = "compression")]
+impl Codec for Zstd {
+    fn name(&self) -> &str {
+        "Zstd"
+    }
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(zstd::stream::encode_all(data, 0)?)
+    }
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()> {
+        read_exact(
+            zstd::stream::read::Decoder::new(data)?,
+            output,
+            Compression::Zstd,
+        )
+    }
+}
+
+#[cfg(feature = "compression")]
+pub struct Lz4;
+#[cfg(feature = "compression")]
+impl Codec for Lz4 {
+    fn name(&self) -> &str {
+        "LZ4"
+    }
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(lz4_flex::block::compress(data))
+    }
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()> {
+        match lz4_flex::block::decompress_into(data, output) {
+            Ok(len) if len == output.len() => Ok(()),
+            _ => Err(Error::DecompressionFailed(Compression::LZ4)),
+        }
+    }
+}
+
+/// Oodle Mermaid, loaded at runtime by `oodle_loader` when the `oodle` feature is enabled
+#[cfg(feature = "compression")]
+pub struct Oodle;
+#[cfg(feature = "compression")]
+impl Codec for Oodle {
+    fn name(&self) -> &str {
+        "Oodle"
+    }
+    #[allow(unused)]
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        #[cfg(not(feature = "oodle"))]
+        return Err(Error::Oodle);
+        #[cfg(feature = "oodle")]
+        Ok(oodle_loader::oodle()?.compress(
+            data,
+            oodle_loader::Compressor::Mermaid,
+            oodle_loader::CompressionLevel::Normal,
+        )?)
+    }
+    #[allow(unused)]
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<()> {
+        #[cfg(not(feature = "oodle"))]
+        return Err(Error::Oodle);
+        #[cfg(feature = "oodle")]
+        match oodle_loader::oodle()?.decompress(data, output) {
+            0 => Err(Error::DecompressionFailed(Compression::Oodle)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{entry::Entry, ext::ReadExt, Error, PakReader, PakWriter};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Rewrites the entries of `pak` contiguously, dropping data left unreachable by replaced or
+/// removed entries and padding between entries.
+///
+/// Entries are copied in the order of their original offsets without recompressing or
+/// reencrypting them, and the index keeps the metadata of `pak`. Returns the writer along with
+/// the number of bytes reclaimed.
+pub fn compact<R, W>(pak: &PakReader, reader: &mut R, writer: W) -> Result<(W, u64)>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let version = pak.version();
+    let original_len = reader.seek(SeekFrom::End(0))?;
+
+    let mut entries = pak.entries().collect::<Vec<_>>();
+    entries.sort_by_key(|(_, entry)| entry.offset);
+
+    let mut pak_writer = PakWriter::with_metadata_of(writer, pak);
+    for (path, entry) in entries {
+        let mut entry = entry.clone();
+        let data = match entry.is_deleted() {
+            true => vec![],
+            false => {
+                // encoded index entries lack the hash, which only the inline header has
+                if entry.hash.is_none() {
+                    reader.seek(SeekFrom::Start(entry.offset))?;
+                    entry.hash = Entry::read(reader, version)?.hash;
+                }
+                let range = entry.stored_range(version);
+                reader.seek(SeekFrom::Start(range.end - entry.data_size()))?;
+                reader.read_len(entry.data_size() as usize)?
+            }
+        };
+        pak_writer.write_raw_entry(path.to_owned(), entry, &data)?;
+    }
+
+    let mut writer = pak_writer.write_index()?;
+    let compacted_len = writer.stream_position()?;
+    Ok((writer, original_len.saturating_sub(compacted_len)))
+}
@@ -2,48 +2,40 @@ use std::io::Write;
 
 use crate::{
     entry::{Block, Entry},
-    Compression, Error, Hash, Version, VersionMajor,
+    Codecs, Compression, EncryptionScheme, Error, Hash, Version, VersionMajor,
 };
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub(crate) fn get_limit(path: &str) -> usize {
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(&[0x11, 0x22, 0x33, 0x44]);
-    hasher.update(path.to_ascii_lowercase().as_bytes());
-    let limit =
-        ((u64::from_le_bytes(hasher.finalize().as_bytes()[0..8].try_into().unwrap()) % 0x3d) * 63
-            + 319)
-            & 0xffffffffffffffc0;
-    if limit == 0 {
-        0x1000
-    } else {
-        limit as usize
-    }
-}
-
 pub(crate) fn pad_length(length: usize, alignment: usize) -> usize {
     length + (alignment - length % alignment) % alignment
 }
 
-pub(crate) fn pad_zeros_to_alignment(v: &mut Vec<u8>, alignment: usize) {
-    v.resize(pad_length(v.len(), alignment), 0);
+/// Pads `v` by repeating its leading bytes, as UnrealPak does before encrypting data
+pub(crate) fn pad_data_to_alignment(v: &mut Vec<u8>, alignment: usize) {
+    let len = v.len();
+    for i in len..pad_length(len, alignment) {
+        v.push(v[i - len]);
+    }
 }
 
+/// Encrypts whole AES blocks of `bytes` with the block transform of `scheme`
 #[cfg(feature = "encryption")]
-pub(crate) fn encrypt(key: &aes::Aes256, bytes: &mut [u8]) {
-    use aes::cipher::BlockEncrypt;
-    panic!("Stellarblade doesnt use encryption");
+pub(crate) fn encrypt(scheme: &dyn EncryptionScheme, key: &aes::Aes256, bytes: &mut [u8]) {
+    for block in bytes.chunks_mut(16) {
+        scheme.encrypt_block(key, aes::Block::from_mut_slice(block));
+    }
 }
 
 #[cfg(feature = "encryption")]
-pub(crate) fn decrypt(key: &super::Key, bytes: &mut [u8]) -> Result<(), super::Error> {
+pub(crate) fn decrypt(
+    scheme: &dyn EncryptionScheme,
+    key: &super::Key,
+    bytes: &mut [u8],
+) -> Result<(), super::Error> {
     if let super::Key::Some(key) = key {
-        use aes::cipher::BlockDecrypt;
-        for chunk in bytes.chunks_mut(16) {
-            chunk.chunks_mut(4).for_each(|c| c.reverse());
-            key.decrypt_block(aes::Block::from_mut_slice(chunk));
-            chunk.chunks_mut(4).for_each(|c| c.reverse());
+        for block in bytes.chunks_mut(16) {
+            scheme.decrypt_block(key, aes::Block::from_mut_slice(block));
         }
         Ok(())
     } else {
@@ -51,6 +43,21 @@ pub(crate) fn decrypt(key: &super::Key, bytes: &mut [u8]) -> Result<(), super::E
     }
 }
 
+/// Decrypts an index buffer, see [`EncryptionScheme::decrypt_index`]
+#[cfg(feature = "encryption")]
+pub(crate) fn decrypt_index(
+    scheme: &dyn EncryptionScheme,
+    key: &super::Key,
+    bytes: &mut [u8],
+) -> Result<(), super::Error> {
+    if let super::Key::Some(key) = key {
+        scheme.decrypt_index(key, bytes);
+        Ok(())
+    } else {
+        Err(super::Error::Encrypted)
+    }
+}
+
 pub struct PartialEntry<D: AsRef<[u8]>> {
     compression: Option<Compression>,
     compressed_size: u64,
@@ -59,6 +66,7 @@ pub struct PartialEntry<D: AsRef<[u8]>> {
     data: PartialEntryData<D>,
     encrypted: bool,
     hash: Hash,
+    pub(crate) timestamp: Option<u64>,
 }
 pub(crate) struct PartialBlock {
     uncompressed_size: usize,
@@ -80,7 +88,6 @@ impl<D: AsRef<[u8]>> AsRef<[u8]> for PartialEntryData<D> {
     }
 }
 
-#[cfg(feature = "compression")]
 fn get_compression_slot(
     version: Version,
     compression_slots: &mut Vec<Option<Compression>>,
@@ -89,7 +96,7 @@ fn get_compression_slot(
     let slot = compression_slots
         .iter()
         .enumerate()
-        .find(|(_, s)| **s == Some(compression));
+        .find(|(_, s)| s.as_ref() == Some(&compression));
     Ok(if let Some((i, _)) = slot {
         // existing found
         i
@@ -110,7 +117,15 @@ fn get_compression_slot(
             *empty_slot = Some(compression);
             i
         } else {
-            // no empty slot found, add a new one
+            // no empty slot found, add a new one if the footer has room for it
+            let max = if version < Version::V8B { 4 } else { 5 };
+            if compression_slots.len() >= max {
+                return Err(Error::TooManyCompressionSlots {
+                    version,
+                    count: compression_slots.len() + 1,
+                    max,
+                });
+            }
             compression_slots.push(Some(compression));
             compression_slots.len() - 1
         }
@@ -118,19 +133,30 @@ fn get_compression_slot(
 }
 
 impl<D: AsRef<[u8]>> PartialEntry<D> {
+    /// Sets the timestamp stored for V1 paks, see [`crate::utils::ticks`]
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+    /// Size of the inline entry header preceding the data
+    pub(crate) fn header_size(&self, version: Version) -> u64 {
+        let block_count = match &self.data {
+            PartialEntryData::Slice(_) => 0,
+            PartialEntryData::Blocks { blocks, .. } => blocks.len() as u32,
+        };
+        Entry::get_serialized_size(version, self.compression.as_ref().map(|_| 0), block_count)
+    }
     pub(crate) fn build_entry(
         &self,
         version: Version,
-        #[allow(unused)] compression_slots: &mut Vec<Option<Compression>>,
+        compression_slots: &mut Vec<Option<Compression>>,
         file_offset: u64,
     ) -> Result<Entry> {
-        #[cfg(feature = "compression")]
         let compression_slot = self
             .compression
+            .clone()
             .map(|c| get_compression_slot(version, compression_slots, c))
             .transpose()?;
-        #[cfg(not(feature = "compression"))]
-        let compression_slot = None;
 
         let blocks = match &self.data {
             PartialEntryData::Slice(_) => None,
@@ -151,8 +177,12 @@ impl<D: AsRef<[u8]>> PartialEntry<D> {
                             .iter()
                             .map(|block| {
                                 let start = offset;
-                                offset += block.compressed_size as u64;
-                                let end = offset;
+                                let end = start + block.compressed_size as u64;
+                                // encrypted blocks are padded to the AES block size
+                                offset += match self.encrypted {
+                                    true => pad_length(block.compressed_size, 16) as u64,
+                                    false => block.compressed_size as u64,
+                                };
                                 Block { start, end }
                             })
                             .collect(),
@@ -166,7 +196,7 @@ impl<D: AsRef<[u8]>> PartialEntry<D> {
             compressed: self.compressed_size,
             uncompressed: self.uncompressed_size,
             compression_slot,
-            timestamp: None,
+            timestamp: self.timestamp,
             hash: Some(self.hash),
             blocks,
             flags: self.encrypted as u8,
@@ -186,19 +216,26 @@ impl<D: AsRef<[u8]>> PartialEntry<D> {
     }
 }
 
+/// Default size of the uncompressed blocks an entry is split into when compressing
+pub(crate) const COMPRESSION_BLOCK_SIZE: u32 = 0x10000;
+
+/// UnrealPak shrinks the block size of entries smaller than one block
+fn entry_block_size(block_size: u32, uncompressed_size: u64) -> u32 {
+    u32::try_from(uncompressed_size).map_or(block_size, |size| block_size.min(size))
+}
+
 pub(crate) fn build_partial_entry<D>(
+    codecs: &Codecs,
     allowed_compression: &[Compression],
     data: D,
     #[allow(unused)] key: &super::Key,
-    path: &str,
+    #[allow(unused)] encryption: &dyn EncryptionScheme,
+    #[allow(unused)] path: &str,
+    block_size: u32,
 ) -> Result<PartialEntry<D>>
 where
     D: AsRef<[u8]>,
 {
-    // TODO hash needs to be post-compression/encryption
-    use sha1::{Digest, Sha1};
-    let mut hasher = Sha1::new();
-
     let mut encrypted = false;
     #[cfg(feature = "encryption")]
     if let super::Key::Some(_) = key {
@@ -210,30 +247,26 @@ where
     let uncompressed_size = data.as_ref().len() as u64;
     let compression_block_size;
 
-    let mut data = match compression {
-        #[cfg(not(feature = "compression"))]
-        Some(_) => {
-            unreachable!("should not be able to reach this point without compression feature")
-        }
-        #[cfg(feature = "compression")]
+    let mut data = match &compression {
         Some(compression) if uncompressed_size > 0 => {
+            let codec = codecs.codec(compression)?;
             // https://github.com/EpicGames/UnrealEngine/commit/3aad0ff7976be1073005dca2c1282af548b45d89
             // Block size must fit into flags field or it may cause unreadable paks for earlier Unreal Engine versions
-            compression_block_size = 0x10000;
             let mut compressed_data = vec![];
             let mut blocks = vec![];
-            for chunk in data.as_ref().chunks(compression_block_size as usize) {
-                let mut data = compress(compression, chunk)?;
+            for chunk in data.as_ref().chunks(block_size as usize) {
+                let mut data = codec.compress(chunk)?;
+                let compressed_size = data.len();
                 if encrypted {
-                    pad_zeros_to_alignment(&mut data, 16);
+                    pad_data_to_alignment(&mut data, 16);
                 }
                 compressed_data.extend_from_slice(&data);
-                hasher.update(&data);
                 blocks.push(PartialBlock {
                     uncompressed_size: chunk.len(),
-                    compressed_size: data.len(),
+                    compressed_size,
                 })
             }
+            compression_block_size = entry_block_size(block_size, uncompressed_size);
 
             PartialEntryData::Blocks {
                 data: compressed_data,
@@ -243,10 +276,11 @@ where
         _ => {
             compression = None;
             compression_block_size = 0;
-            hasher.update(data.as_ref());
             PartialEntryData::Slice(data)
         }
     };
+    // compressed blocks are stored padded while uncompressed data is sized without padding
+    let compressed_size = data.as_ref().len();
 
     #[cfg(feature = "encryption")]
     if let super::Key::Some(key) = key {
@@ -264,61 +298,71 @@ where
         match &mut data {
             PartialEntryData::Slice(_) => unreachable!(),
             PartialEntryData::Blocks { data, .. } => {
-                let limit = crate::data::get_limit(path);
-                let limit = if limit > data.len() {
-                    pad_zeros_to_alignment(data, 16);
-                    data.len()
-                } else {
-                    limit
-                };
-                encrypt(key, &mut data[..limit]);
+                pad_data_to_alignment(data, 16);
+                let len = encryption.encrypted_len(path, data.len());
+                encrypt(encryption, key, &mut data[..len]);
             }
         }
     }
 
+    // the hash covers the data as stored, i.e. after compression and encryption
+    use sha1::{Digest, Sha1};
+    let hash = Hash(Sha1::digest(&data.as_ref()[..compressed_size]).into());
+
     Ok(PartialEntry {
         compression,
-        compressed_size: data.as_ref().len() as u64,
+        compressed_size: compressed_size as u64,
         uncompressed_size,
         compression_block_size,
         data,
-        hash: Hash(hasher.finalize().into()),
+        hash,
         encrypted,
+        timestamp: None,
     })
 }
 
-#[cfg(feature = "compression")]
-fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>> {
-    use std::io::Write;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    let compressed = match compression {
-        Compression::Zlib => {
-            let mut compress =
-                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
-            compress.write_all(data.as_ref())?;
-            compress.finish()?
+    /// Stores every block as a single byte without looking at it
+    struct Stub;
+
+    impl crate::Codec for Stub {
+        fn name(&self) -> &str {
+            "Stub"
         }
-        Compression::Gzip => {
-            let mut compress =
-                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
-            compress.write_all(data.as_ref())?;
-            compress.finish()?
+        fn compress(&self, _data: &[u8]) -> Result<Vec<u8>> {
+            Ok(vec![0])
         }
-        Compression::Zstd => zstd::stream::encode_all(data, 0)?,
-        Compression::LZ4 => lz4_flex::block::compress(data),
-        Compression::Oodle => {
-            #[cfg(not(feature = "oodle"))]
-            return Err(super::Error::Oodle);
-            #[cfg(feature = "oodle")]
-            {
-                oodle_loader::oodle().unwrap().compress(
-                    data.as_ref(),
-                    oodle_loader::Compressor::Mermaid,
-                    oodle_loader::CompressionLevel::Normal,
-                )?
-            }
+        fn decompress(&self, _data: &[u8], _output: &mut [u8]) -> Result<()> {
+            Err(Error::Other("Stub cannot decompress".to_owned()))
         }
-    };
+    }
 
-    Ok(compressed)
+    #[test]
+    fn test_block_size() {
+        let mut codecs = Codecs::default();
+        codecs.register(Stub);
+        let entry = build_partial_entry(
+            &codecs,
+            &[Compression::Custom("Stub".to_owned())],
+            vec![0u8; 0x100],
+            &crate::Key::None,
+            &crate::UnrealStandard,
+            "a.bin",
+            COMPRESSION_BLOCK_SIZE,
+        )
+        .unwrap();
+        assert_eq!(entry.compression_block_size, 0x100);
+
+        let block_size = |size| entry_block_size(COMPRESSION_BLOCK_SIZE, size);
+        assert_eq!(block_size(0x100), 0x100);
+        assert_eq!(
+            block_size(COMPRESSION_BLOCK_SIZE as u64 + 1),
+            COMPRESSION_BLOCK_SIZE
+        );
+        assert_eq!(block_size(0x1_0000_0000), COMPRESSION_BLOCK_SIZE);
+        assert_eq!(block_size(0x1_0000_0100), COMPRESSION_BLOCK_SIZE);
+    }
 }
@@ -0,0 +1,197 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, Write};
+
+use crate::{entry::Entry, Compression, Error, PakReader, Version};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Differences between two paks, as produced by [`diff`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PakDiff {
+    pub mount_point: Option<(String, String)>,
+    pub version: Option<(Version, Version)>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<EntryDiff>,
+}
+
+impl PakDiff {
+    /// Whether both paks have the same header and identical entries
+    pub fn is_empty(&self) -> bool {
+        self.mount_point.is_none()
+            && self.version.is_none()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+/// An entry present in both paks whose content or metadata differs
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryDiff {
+    pub path: String,
+    /// Decompressed content differs
+    pub content: bool,
+    pub metadata: Vec<MetadataChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataChange {
+    Compression(Option<Compression>, Option<Compression>),
+    CompressionBlockSize(u32, u32),
+    Compressed(u64, u64),
+    Uncompressed(u64, u64),
+    Encrypted(bool, bool),
+}
+
+impl std::fmt::Display for MetadataChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn compression(c: &Option<Compression>) -> String {
+            c.as_ref()
+                .map_or_else(|| "None".to_owned(), |c| c.to_string())
+        }
+        match self {
+            MetadataChange::Compression(a, b) => {
+                write!(f, "compression {} -> {}", compression(a), compression(b))
+            }
+            MetadataChange::CompressionBlockSize(a, b) => {
+                write!(f, "compression block size {a:#x} -> {b:#x}")
+            }
+            MetadataChange::Compressed(a, b) => write!(f, "compressed size {a} -> {b}"),
+            MetadataChange::Uncompressed(a, b) => write!(f, "uncompressed size {a} -> {b}"),
+            MetadataChange::Encrypted(a, b) => write!(f, "encrypted {a} -> {b}"),
+        }
+    }
+}
+
+impl std::fmt::Display for PakDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((a, b)) = &self.mount_point {
+            writeln!(f, "mount point: {a:?} -> {b:?}")?;
+        }
+        if let Some((a, b)) = &self.version {
+            writeln!(f, "version: {a} -> {b}")?;
+        }
+        for path in &self.added {
+            writeln!(f, "+ {path}")?;
+        }
+        for path in &self.removed {
+            writeln!(f, "- {path}")?;
+        }
+        for entry in &self.modified {
+            write!(f, "~ {}", entry.path)?;
+            if entry.content {
+                write!(f, " (content)")?;
+            }
+            for change in &entry.metadata {
+                write!(f, ", {change}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the entries of two paks.
+///
+/// Entries present in both paks are compared by a SHA1 of their decompressed content, as
+/// [`Entry::hash`] is computed over the stored (compressed) data.
+pub fn diff<RA: Read + Seek, RB: Read + Seek>(
+    a: &PakReader,
+    reader_a: &mut RA,
+    b: &PakReader,
+    reader_b: &mut RB,
+) -> Result<PakDiff> {
+    let mut diff = PakDiff {
+        mount_point: (a.mount_point() != b.mount_point())
+            .then(|| (a.mount_point().to_owned(), b.mount_point().to_owned())),
+        version: (a.version() != b.version()).then_some((a.version(), b.version())),
+        ..Default::default()
+    };
+
+    let files_a: BTreeSet<String> = a.files().into_iter().collect();
+    let files_b: BTreeSet<String> = b.files().into_iter().collect();
+
+    diff.added = files_b.difference(&files_a).cloned().collect();
+    diff.removed = files_a.difference(&files_b).cloned().collect();
+
+    for path in files_a.intersection(&files_b) {
+        let entry_a = a.get_file_entry(path)?;
+        let entry_b = b.get_file_entry(path)?;
+
+        let metadata = metadata_changes(a, &entry_a, b, &entry_b);
+        let content = content_hash(a, path, reader_a)? != content_hash(b, path, reader_b)?;
+
+        if content || !metadata.is_empty() {
+            diff.modified.push(EntryDiff {
+                path: path.clone(),
+                content,
+                metadata,
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
+fn metadata_changes(
+    a: &PakReader,
+    entry_a: &Entry,
+    b: &PakReader,
+    entry_b: &Entry,
+) -> Vec<MetadataChange> {
+    let mut changes = vec![];
+
+    let compression_a = a.entry_compression(entry_a);
+    let compression_b = b.entry_compression(entry_b);
+    if compression_a != compression_b {
+        changes.push(MetadataChange::Compression(compression_a, compression_b));
+    }
+    if entry_a.compression_block_size != entry_b.compression_block_size {
+        changes.push(MetadataChange::CompressionBlockSize(
+            entry_a.compression_block_size,
+            entry_b.compression_block_size,
+        ));
+    }
+    if entry_a.compressed != entry_b.compressed {
+        changes.push(MetadataChange::Compressed(
+            entry_a.compressed,
+            entry_b.compressed,
+        ));
+    }
+    if entry_a.uncompressed != entry_b.uncompressed {
+        changes.push(MetadataChange::Uncompressed(
+            entry_a.uncompressed,
+            entry_b.uncompressed,
+        ));
+    }
+    if entry_a.is_encrypted() != entry_b.is_encrypted() {
+        changes.push(MetadataChange::Encrypted(
+            entry_a.is_encrypted(),
+            entry_b.is_encrypted(),
+        ));
+    }
+    changes
+}
+
+/// SHA1 of the decompressed content of an entry
+pub(crate) fn content_hash<R: Read + Seek>(
+    pak: &PakReader,
+    path: &str,
+    reader: &mut R,
+) -> Result<[u8; 20]> {
+    let mut hasher = HashWriter(<sha1::Sha1 as sha1::Digest>::new());
+    pak.read_file(path, reader, &mut hasher)?;
+    Ok(sha1::Digest::finalize(hasher.0).into())
+}
+
+struct HashWriter(sha1::Sha1);
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        sha1::Digest::update(&mut self.0, buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
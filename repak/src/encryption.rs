@@ -0,0 +1,170 @@
+//! How entries and index buffers are encrypted with the AES key of a pak.
+
+use std::sync::Arc;
+
+/// Encryption layout selected with [`crate::PakBuilder::encryption`]
+pub trait EncryptionScheme: std::fmt::Debug + Send + Sync {
+    /// Number of leading bytes of the stored data of the entry at `path` which are encrypted.
+    /// `len` is the length of the data padded to the AES block size and `path` is relative to
+    /// the root, e.g. `SB/Content/Art/a.uasset`.
+    fn encrypted_len(&self, path: &str, len: usize) -> usize;
+
+    #[cfg(feature = "encryption")]
+    fn encrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block);
+
+    #[cfg(feature = "encryption")]
+    fn decrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block);
+
+    /// Encrypts the index, path hash index or full directory index, padded to the AES block size
+    #[cfg(feature = "encryption")]
+    fn encrypt_index(&self, key: &aes::Aes256, data: &mut [u8]) {
+        for block in data.chunks_mut(16) {
+            self.encrypt_block(key, aes::Block::from_mut_slice(block));
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt_index(&self, key: &aes::Aes256, data: &mut [u8]) {
+        for block in data.chunks_mut(16) {
+            self.decrypt_block(key, aes::Block::from_mut_slice(block));
+        }
+    }
+}
+
+/// Whole entries encrypted with AES-256 in ECB mode, as written by UnrealPak
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnrealStandard;
+
+impl EncryptionScheme for UnrealStandard {
+    fn encrypted_len(&self, _path: &str, len: usize) -> usize {
+        len
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block) {
+        use aes::cipher::BlockEncrypt;
+        key.encrypt_block(block);
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block) {
+        use aes::cipher::BlockDecrypt;
+        key.decrypt_block(block);
+    }
+}
+
+/// Stellar Blade encrypts only a prefix of each entry, its length derived from a salted blake3
+/// hash of the lowercased path, and byte swaps each 32 bit word around the AES transform
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StellarBladePartial;
+
+impl StellarBladePartial {
+    fn limit(path: &str) -> usize {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&[0x11, 0x22, 0x33, 0x44]);
+        hasher.update(path.to_ascii_lowercase().as_bytes());
+        let hash = u64::from_le_bytes(hasher.finalize().as_bytes()[0..8].try_into().unwrap());
+        match ((hash % 0x3d) * 63 + 319) & !0x3f {
+            0 => 0x1000,
+            limit => limit as usize,
+        }
+    }
+}
+
+impl EncryptionScheme for StellarBladePartial {
+    fn encrypted_len(&self, path: &str, len: usize) -> usize {
+        Self::limit(path).min(len)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block) {
+        use aes::cipher::BlockEncrypt;
+        block.chunks_mut(4).for_each(|c| c.reverse());
+        key.encrypt_block(block);
+        block.chunks_mut(4).for_each(|c| c.reverse());
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt_block(&self, key: &aes::Aes256, block: &mut aes::Block) {
+        use aes::cipher::BlockDecrypt;
+        block.chunks_mut(4).for_each(|c| c.reverse());
+        key.decrypt_block(block);
+        block.chunks_mut(4).for_each(|c| c.reverse());
+    }
+}
+
+/// Built-in schemes by name, as stored in a [`crate::GameProfile`]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    strum::Display,
+    strum::EnumString,
+    strum::VariantNames,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Builtin {
+    #[default]
+    UnrealStandard,
+    StellarBladePartial,
+}
+
+impl Builtin {
+    /// Scheme of paks read or written without [`crate::PakBuilder::encryption`]
+    pub const PAK_DEFAULT: Builtin = Builtin::StellarBladePartial;
+    /// Scheme of IoStore containers read without [`crate::PakBuilder::encryption`]
+    pub const IOSTORE_DEFAULT: Builtin = Builtin::UnrealStandard;
+
+    pub fn scheme(self) -> Arc<dyn EncryptionScheme> {
+        match self {
+            Builtin::UnrealStandard => Arc::new(UnrealStandard),
+            Builtin::StellarBladePartial => Arc::new(StellarBladePartial),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_len() {
+        assert_eq!(
+            UnrealStandard.encrypted_len("SB/Content/a.uasset", 0x20000),
+            0x20000
+        );
+        for path in ["SB/Content/a.uasset", "SB/Content/Art/B.uexp"] {
+            let len = StellarBladePartial.encrypted_len(path, 0x20000);
+            assert_eq!(len % 64, 0);
+            assert!(len > 0 && len <= 0x1000);
+            assert_eq!(
+                StellarBladePartial.encrypted_len(&path.to_ascii_uppercase(), 0x20000),
+                len
+            );
+            assert_eq!(StellarBladePartial.encrypted_len(path, 32), 32);
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_block_transform() {
+        use aes::cipher::KeyInit;
+        let key = aes::Aes256::new_from_slice(&[7; 32]).unwrap();
+        let plain = aes::Block::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        for scheme in [Builtin::UnrealStandard, Builtin::StellarBladePartial] {
+            let scheme = scheme.scheme();
+            let mut block = plain;
+            scheme.encrypt_block(&key, &mut block);
+            assert_ne!(block, plain);
+            scheme.decrypt_block(&key, &mut block);
+            assert_eq!(block, plain);
+        }
+        let (mut standard, mut partial) = (plain, plain);
+        UnrealStandard.encrypt_block(&key, &mut standard);
+        StellarBladePartial.encrypt_block(&key, &mut partial);
+        assert_ne!(standard, partial);
+    }
+}
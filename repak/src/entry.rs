@@ -1,6 +1,8 @@
-use crate::{data::build_partial_entry, Error, Hash};
+use crate::Hash;
 
-use super::{ext::BoolExt, ext::ReadExt, Compression, Version, VersionMajor};
+use super::{
+    ext::BoolExt, ext::ReadExt, Codecs, Compression, EncryptionScheme, Version, VersionMajor,
+};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use std::io;
 
@@ -11,6 +13,7 @@ pub enum EntryLocation {
 }
 
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     pub start: u64,
     pub end: u64,
@@ -36,6 +39,22 @@ fn align(offset: u64) -> u64 {
     (offset + 15) & !15
 }
 
+/// Converts `value` for a field narrower than 64 bits, failing with [`super::Error::FieldOverflow`]
+pub(crate) fn narrow<T: TryFrom<u64>>(
+    field: &'static str,
+    value: u64,
+    max: u64,
+) -> Result<T, super::Error> {
+    match T::try_from(value) {
+        Ok(narrowed) if value <= max => Ok(narrowed),
+        _ => Err(super::Error::FieldOverflow { field, value, max }),
+    }
+}
+
+// limits of the bit fields of encoded entries
+const ENCODED_MAX_BLOCK_COUNT: u64 = 0xffff;
+const ENCODED_MAX_COMPRESSION: u64 = 0x3f;
+
 fn compression_index_size(version: Version) -> CompressionIndexSize {
     match version {
         Version::V8A => CompressionIndexSize::U8,
@@ -48,7 +67,8 @@ enum CompressionIndexSize {
     U32,
 }
 
-#[derive(Debug,Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entry {
     pub offset: u64,
     pub compressed: u64,
@@ -68,6 +88,39 @@ impl Entry {
     pub fn is_deleted(&self) -> bool {
         0 != (self.flags >> 1) & 1
     }
+    /// Whether the entry fits the bit fields of [`Entry::write_encoded`]. Entries which do not,
+    /// e.g. ones split into more than 65535 blocks, are stored unencoded in the index instead.
+    pub fn is_encodable(&self) -> bool {
+        let Some(slot) = self.compression_slot else {
+            return true;
+        };
+        let blocks = self.blocks.as_deref().unwrap_or_default();
+        (slot as u64) < ENCODED_MAX_COMPRESSION
+            && blocks.len() as u64 <= ENCODED_MAX_BLOCK_COUNT
+            // block sizes are implied by the entry size for a single unencrypted block
+            && (blocks.len() <= 1 && !self.is_encrypted()
+                || blocks.iter().all(|b| b.end - b.start <= u32::MAX as u64))
+    }
+    /// Size of the stored data including AES padding
+    pub(crate) fn data_size(&self) -> u64 {
+        let align = |size: u64| match self.is_encrypted() {
+            true => (size + 15) & !15,
+            false => size,
+        };
+        match &self.blocks {
+            Some(blocks) if !blocks.is_empty() => blocks
+                .iter()
+                .map(|block| align(block.end - block.start))
+                .sum(),
+            _ => align(self.compressed),
+        }
+    }
+    /// Byte range of the inline header and data of the entry
+    pub(crate) fn stored_range(&self, version: super::Version) -> std::ops::Range<u64> {
+        let block_count = self.blocks.as_ref().map_or(0, |b| b.len() as u32);
+        let header = Self::get_serialized_size(version, self.compression_slot, block_count);
+        self.offset..self.offset + header + self.data_size()
+    }
     pub fn get_serialized_size(
         version: super::Version,
         compression: Option<u32>,
@@ -98,23 +151,6 @@ impl Entry {
         size
     }
 
-    pub(crate) fn write_file<W: io::Write + io::Seek>(
-        writer: &mut W,
-        version: Version,
-        compression_slots: &mut Vec<Option<Compression>>,
-        allowed_compression: &[Compression],
-        data: &[u8],
-        #[allow(unused)] key: &super::Key,
-        path: &str,
-    ) -> Result<Self, Error> {
-        let partial_entry = build_partial_entry(allowed_compression, data, key, path)?;
-        let stream_position = writer.stream_position()?;
-        let entry = partial_entry.build_entry(version, compression_slots, stream_position)?;
-        entry.write(writer, version, crate::entry::EntryLocation::Data)?;
-        partial_entry.write_data(writer)?;
-        Ok(entry)
-    }
-
     pub fn read<R: io::Read>(
         reader: &mut R,
         version: super::Version,
@@ -133,7 +169,7 @@ impl Entry {
         let timestamp = (ver == VersionMajor::Initial).then_try(|| reader.read_u64::<LE>())?;
         let hash = Some(Hash(reader.read_guid()?));
         let blocks = (ver >= VersionMajor::CompressionEncryption && compression.is_some())
-            .then_try(|| reader.read_array(Block::read))?;
+            .then_try(|| ReadExt::read_array(reader, Block::read))?;
         let flags = (ver >= VersionMajor::CompressionEncryption)
             .then_try(|| reader.read_u8())?
             .unwrap_or(0);
@@ -165,10 +201,16 @@ impl Entry {
         })?;
         writer.write_u64::<LE>(self.compressed)?;
         writer.write_u64::<LE>(self.uncompressed)?;
-        let compression = self.compression_slot.map_or(0, |n| n + 1);
+        let compression = self.compression_slot.map_or(0, |n| n as u64 + 1);
         match compression_index_size(version) {
-            CompressionIndexSize::U8 => writer.write_u8(compression.try_into().unwrap())?,
-            CompressionIndexSize::U32 => writer.write_u32::<LE>(compression)?,
+            CompressionIndexSize::U8 => {
+                writer.write_u8(narrow("compression index", compression, u8::MAX as u64)?)?
+            }
+            CompressionIndexSize::U32 => writer.write_u32::<LE>(narrow(
+                "compression index",
+                compression,
+                u32::MAX as u64,
+            )?)?,
         }
 
         if version.version_major() == VersionMajor::Initial {
@@ -181,7 +223,11 @@ impl Entry {
         }
         if version.version_major() >= VersionMajor::CompressionEncryption {
             if let Some(blocks) = &self.blocks {
-                writer.write_u32::<LE>(blocks.len() as u32)?;
+                writer.write_u32::<LE>(narrow(
+                    "compression block count",
+                    blocks.len() as u64,
+                    u32::MAX as u64,
+                )?)?;
                 for block in blocks {
                     block.write(writer)?;
                 }
@@ -275,11 +321,20 @@ impl Entry {
         if (compression_block_size << 11) != self.compression_block_size {
             compression_block_size = 0x3f;
         }
-        let compression_blocks_count = if self.compression_slot.is_some() {
-            self.blocks.as_ref().unwrap().len() as u32
+        let compression_blocks_count: u32 = if self.compression_slot.is_some() {
+            narrow(
+                "compression block count",
+                self.blocks.as_ref().unwrap().len() as u64,
+                ENCODED_MAX_BLOCK_COUNT,
+            )?
         } else {
             0
         };
+        let compression: u32 = narrow(
+            "compression index",
+            self.compression_slot.map_or(0, |n| n as u64 + 1),
+            ENCODED_MAX_COMPRESSION,
+        )?;
         let is_size_32_bit_safe = self.compressed <= u32::MAX as u64;
         let is_uncompressed_size_32_bit_safe = self.uncompressed <= u32::MAX as u64;
         let is_offset_32_bit_safe = self.offset <= u32::MAX as u64;
@@ -287,7 +342,7 @@ impl Entry {
         let flags = (compression_block_size)
             | (compression_blocks_count << 6)
             | ((self.is_encrypted() as u32) << 22)
-            | (self.compression_slot.map_or(0, |n| n + 1) << 23)
+            | (compression << 23)
             | ((is_size_32_bit_safe as u32) << 29)
             | ((is_uncompressed_size_32_bit_safe as u32) << 30)
             | ((is_offset_32_bit_safe as u32) << 31);
@@ -321,8 +376,11 @@ impl Entry {
             let blocks = self.blocks.as_ref().unwrap();
             if blocks.len() > 1 || self.is_encrypted() {
                 for b in blocks {
-                    let block_size = b.end - b.start;
-                    writer.write_u32::<LE>(block_size.try_into().unwrap())?;
+                    writer.write_u32::<LE>(narrow(
+                        "compression block size",
+                        b.end - b.start,
+                        u32::MAX as u64,
+                    )?)?;
                 }
             }
         }
@@ -330,18 +388,20 @@ impl Entry {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn read_file<R: io::Read + io::Seek, W: io::Write>(
         &self,
         reader: &mut R,
         version: Version,
         compression: &[Option<Compression>],
+        codecs: &Codecs,
         #[allow(unused)] key: &super::Key,
+        #[allow(unused)] encryption: &dyn EncryptionScheme,
         buf: &mut W,
-        path: &str,
+        #[allow(unused)] path: &str,
     ) -> Result<(), super::Error> {
         reader.seek(io::SeekFrom::Start(self.offset))?;
         Entry::read(reader, version)?;
-        #[cfg(any(feature = "compression", feature = "oodle"))]
         let data_offset = reader.stream_position()?;
 
         #[allow(unused_mut)]
@@ -350,19 +410,17 @@ impl Entry {
             false => self.compressed,
         } as usize)?;
 
-        let limit = crate::data::get_limit(path).min(data.len());
-
         if self.is_encrypted() {
             #[cfg(not(feature = "encryption"))]
             return Err(super::Error::Encryption);
             #[cfg(feature = "encryption")]
             {
-                crate::data::decrypt(key, &mut data[..limit])?;
+                let len = encryption.encrypted_len(path, data.len());
+                crate::data::decrypt(encryption, key, &mut data[..len])?;
                 data.truncate(self.compressed as usize);
             }
         }
 
-        #[cfg(feature = "compression")]
         let ranges = {
             let offset = |index: u64| -> usize {
                 (match version.version_major() >= VersionMajor::RelativeChunkOffsets {
@@ -381,61 +439,38 @@ impl Entry {
             }
         };
 
-        #[cfg(feature = "compression")]
-        macro_rules! decompress {
-            ($decompressor: ty) => {
-                for range in ranges {
-                    io::copy(&mut <$decompressor>::new(&data[range]), buf)?;
-                }
-            };
-        }
-
-        match self.compression_slot.and_then(|c| compression[c as usize]) {
+        let compression = match self.compression_slot {
+            None => None,
+            Some(slot) => Some(
+                compression
+                    .get(slot as usize)
+                    .and_then(Option::as_ref)
+                    .ok_or(super::Error::MissingCompressionSlot(slot))?,
+            ),
+        };
+        match compression {
             None => buf.write_all(&data)?,
-            #[cfg(not(feature = "compression"))]
-            _ => return Err(super::Error::Compression),
-            #[cfg(feature = "compression")]
             Some(comp) => {
+                let codec = codecs.codec(comp)?;
                 let chunk_size = if ranges.len() == 1 {
                     self.uncompressed as usize
                 } else {
                     self.compression_block_size as usize
                 };
 
-                match comp {
-                    Compression::Zlib => decompress!(flate2::read::ZlibDecoder<&[u8]>),
-                    Compression::Gzip => decompress!(flate2::read::GzDecoder<&[u8]>),
-                    Compression::Zstd => {
-                        for range in ranges {
-                            io::copy(&mut zstd::stream::read::Decoder::new(&data[range])?, buf)?;
-                        }
-                    }
-                    Compression::LZ4 => {
-                        let mut decompressed = vec![0; self.uncompressed as usize];
-                        for (decomp_chunk, comp_range) in
-                            decompressed.chunks_mut(chunk_size).zip(ranges)
-                        {
-                            lz4_flex::block::decompress_into(&data[comp_range], decomp_chunk)
-                                .map_err(|_| Error::DecompressionFailed(Compression::LZ4))?;
-                        }
-                        buf.write_all(&decompressed)?;
-                    }
-                    #[cfg(feature = "oodle")]
-                    Compression::Oodle => {
-                        let mut decompressed = vec![0; self.uncompressed as usize];
-                        for (decomp_chunk, comp_range) in
-                            decompressed.chunks_mut(chunk_size).zip(ranges)
-                        {
-                            let out =
-                                oodle_loader::oodle()?.decompress(&data[comp_range], decomp_chunk);
-                            if out == 0 {
-                                return Err(Error::DecompressionFailed(Compression::Oodle));
-                            }
-                        }
-                        buf.write_all(&decompressed)?;
+                let chunk_size = chunk_size.max(1);
+
+                // blocks are decoded one at a time so only a single block is held uncompressed
+                let mut remaining = self.uncompressed as usize;
+                let mut block = vec![0; chunk_size.min(remaining)];
+                for comp_range in ranges {
+                    let len = chunk_size.min(remaining);
+                    if len == 0 {
+                        break;
                     }
-                    #[cfg(not(feature = "oodle"))]
-                    Compression::Oodle => return Err(super::Error::Oodle),
+                    codec.decompress(&data[comp_range], &mut block[..len])?;
+                    buf.write_all(&block[..len])?;
+                    remaining -= len;
                 }
             }
         }
@@ -478,4 +513,70 @@ mod test {
             .unwrap();
         assert_eq!(&data, &out);
     }
+
+    #[test]
+    fn test_encoded_64_bit() {
+        use super::{Block, Entry};
+        let entry = |offset: u64, size: u64, block_count: u64| Entry {
+            offset,
+            compressed: size,
+            uncompressed: size,
+            compression_slot: Some(0),
+            timestamp: None,
+            hash: None,
+            blocks: Some(
+                (0..block_count)
+                    .map(|i| Block {
+                        start: i * size / block_count,
+                        end: (i + 1) * size / block_count,
+                    })
+                    .collect(),
+            ),
+            flags: 0,
+            compression_block_size: 0x10000,
+        };
+
+        let big = entry(0x1_4000_0000, 0x1_2000_0000, 2);
+        assert!(big.is_encodable());
+        let mut out = vec![];
+        big.write_encoded(&mut out).unwrap();
+        let read =
+            Entry::read_encoded(&mut std::io::Cursor::new(&out), super::Version::V11).unwrap();
+        assert_eq!(
+            (read.offset, read.compressed, read.uncompressed),
+            (big.offset, big.compressed, big.uncompressed)
+        );
+        assert_eq!(read.blocks.unwrap().len(), 2);
+        assert!(entry(0, 0x2_0000_0000, 1).is_encodable());
+
+        // 32 bit safe fields are written narrow
+        let small = entry(0x1000, 0x20000, 2);
+        let mut out = vec![];
+        small.write_encoded(&mut out).unwrap();
+        assert_eq!(out.len(), 4 + 4 + 4 + 4 + 2 * 4);
+
+        for (entry, field) in [
+            (
+                entry(0, 0x1_0000_0000 * 0x10, 0x10000),
+                "compression block count",
+            ),
+            (entry(0, 0x4_0000_0000, 2), "compression block size"),
+        ] {
+            assert!(!entry.is_encodable());
+            assert!(matches!(
+                entry.write_encoded(&mut vec![]),
+                Err(crate::Error::FieldOverflow { field: f, .. }) if f == field
+            ));
+        }
+        let mut entry = entry(0, 0x100, 1);
+        entry.compression_slot = Some(0x100);
+        assert!(matches!(
+            entry.write(
+                &mut vec![],
+                super::Version::V8A,
+                super::EntryLocation::Index
+            ),
+            Err(crate::Error::FieldOverflow { .. })
+        ));
+    }
 }
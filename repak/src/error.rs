@@ -46,9 +46,16 @@ pub enum Error {
     #[error("Oodle loader error: {0}")]
     OodleFailed(#[from] oodle_loader::Error),
 
+    #[cfg(feature = "signing")]
+    #[error("RSA error: {0}")]
+    Rsa(#[from] rsa::Error),
+
     #[error("No entry found at {0}")]
     MissingEntry(String),
 
+    #[error("An entry already exists at {0}")]
+    DuplicateEntry(String),
+
     #[error("Prefix \"{prefix}\" does not match path \"{path}\"")]
     PrefixMismatch { prefix: String, path: String },
 
@@ -64,6 +71,29 @@ pub enum Error {
     #[error("{0} decompression failed")]
     DecompressionFailed(Compression),
 
+    #[error("no codec registered for compression method {0}")]
+    MissingCodec(String),
+
+    #[error("unknown compression method {0}")]
+    UnknownCompression(String),
+
+    #[error("entry uses compression slot {0} which is not set in the footer")]
+    MissingCompressionSlot(u32),
+
+    #[error("{count} compression methods exceed the {max} slots of pak version {version}")]
+    TooManyCompressionSlots {
+        version: super::Version,
+        count: usize,
+        max: usize,
+    },
+
+    #[error("{field} of {value} exceeds the maximum of {max}")]
+    FieldOverflow {
+        field: &'static str,
+        value: u64,
+        max: u64,
+    },
+
     #[error("used version {used} but pak is version {version}")]
     Version {
         used: super::VersionMajor,
@@ -79,6 +109,30 @@ pub enum Error {
     #[error("{0}version unsupported or is encrypted (possibly missing --aes-key?)")]
     UnsupportedOrEncrypted(String),
 
+    #[error("found signature magic of {:#x} instead of {:#x}", .0, 0x73832DAAu32)]
+    SignatureMagic(u32),
+
+    #[error("signature does not match master hash")]
+    SignatureMismatch,
+
+    #[error("hash of chunk {0} does not match signature")]
+    ChunkHashMismatch(usize),
+
+    #[error("invalid key {0}")]
+    InvalidKey(String),
+
+    #[error("found utoc magic of {0:?}")]
+    TocMagic([u8; 16]),
+
+    #[error("utoc version {0} unsupported")]
+    TocVersion(u8),
+
+    #[error("line {line}: {message}")]
+    FileList { line: usize, message: String },
+
+    #[error("profile line {line}: {message}")]
+    Profile { line: usize, message: String },
+
     #[error("{0}")]
     Other(String),
 }
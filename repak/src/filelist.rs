@@ -0,0 +1,243 @@
+//! UnrealPak response (`-create=`) and order (`-order=`) files.
+//!
+//! A response file lists one entry per line as `"source" "dest" [-compress] [-encrypt]` where
+//! `dest` is the full virtual path, e.g. `../../../SB/Content/Maps/Map.umap`. An order file lists
+//! `"dest" priority` lines and determines the order in which entry data is written.
+
+use std::collections::HashMap;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Error, PakPath, PakWriter};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A single line of a response file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseEntry {
+    pub source: PathBuf,
+    pub dest: String,
+    pub compress: bool,
+    pub encrypt: bool,
+}
+
+/// Parses a response file. Unknown per-file options (e.g. `-rehydrate`) are ignored.
+pub fn parse_response(text: &str) -> Result<Vec<ResponseEntry>> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let tokens = tokenize(line).map_err(|message| Error::FileList {
+            line: i + 1,
+            message,
+        })?;
+        let (paths, options): (Vec<_>, Vec<_>) = tokens
+            .into_iter()
+            .partition(|(quoted, token)| *quoted || !token.starts_with('-'));
+        match paths.as_slice() {
+            [] => continue,
+            [(_, source), (_, dest)] => entries.push(ResponseEntry {
+                source: source.into(),
+                dest: dest.replace('\\', "/"),
+                compress: options
+                    .iter()
+                    .any(|(_, o)| o.eq_ignore_ascii_case("-compress")),
+                encrypt: options
+                    .iter()
+                    .any(|(_, o)| o.eq_ignore_ascii_case("-encrypt")),
+            }),
+            _ => {
+                return Err(Error::FileList {
+                    line: i + 1,
+                    message: format!("expected source and destination, got {line:?}"),
+                })
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Priorities of an order file by case folded path. Lower priorities are written first.
+#[derive(Debug, Default, Clone)]
+pub struct Order(HashMap<String, u64>);
+
+impl Order {
+    pub fn priority(&self, dest: &str) -> Option<u64> {
+        self.0.get(&PakPath::new(dest).folded()).copied()
+    }
+}
+
+/// Parses an order file. Lines without a priority take their line number.
+pub fn parse_order(text: &str) -> Result<Order> {
+    let mut order = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message| Error::FileList {
+            line: i + 1,
+            message,
+        };
+        let tokens = tokenize(line).map_err(error)?;
+        let (path, priority) = match tokens.as_slice() {
+            [] => continue,
+            [(_, path)] => (path, i as u64),
+            [(_, path), (_, priority)] => (
+                path,
+                priority
+                    .parse()
+                    .map_err(|_| error(format!("invalid priority {priority:?}")))?,
+            ),
+            _ => return Err(error(format!("expected path and priority, got {line:?}"))),
+        };
+        order.insert(PakPath::new(path).folded(), priority);
+    }
+    Ok(Order(order))
+}
+
+/// Sorts entries by their priority in `order`. Entries missing from `order` are placed last and
+/// otherwise keep their relative order.
+pub fn apply_order(entries: &mut [ResponseEntry], order: &Order) {
+    entries.sort_by_key(|entry| order.priority(&entry.dest).unwrap_or(u64::MAX));
+}
+
+/// Deepest directory containing every destination, as UnrealPak derives the mount point
+pub fn mount_point(entries: &[ResponseEntry]) -> String {
+    let mut common: Option<Vec<&str>> = None;
+    for entry in entries {
+        let mut components: Vec<&str> = entry.dest.split('/').collect();
+        components.pop();
+        common = Some(match common {
+            None => components,
+            Some(common) => common
+                .into_iter()
+                .zip(components)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    match common {
+        Some(common) if !common.is_empty() => format!("{}/", common.join("/")),
+        _ => "../../../".to_owned(),
+    }
+}
+
+/// Writes `entries` in order with their compression and encryption flags. File contents are
+/// requested from `data_source` by source path and each destination must be below the mount
+/// point of `pak_writer`. Entries are timestamped with the modification time of their source
+/// file if it exists, which is only stored by V1 paks.
+pub fn write<W, F>(
+    pak_writer: &mut PakWriter<W>,
+    entries: &[ResponseEntry],
+    mut data_source: F,
+) -> Result<()>
+where
+    W: Write + Seek,
+    F: FnMut(&Path) -> Result<Vec<u8>>,
+{
+    let mount_point = PakPath::new(pak_writer.mount_point());
+    for entry in entries {
+        let path = relative_path(&mount_point, &entry.dest)?;
+        let data = data_source(&entry.source)?;
+        let timestamp = std::fs::metadata(&entry.source)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(crate::utils::ticks);
+        pak_writer.write_file_with_flags(&path, entry.compress, entry.encrypt, timestamp, data)?;
+    }
+    Ok(())
+}
+
+fn relative_path(mount_point: &PakPath, dest: &str) -> Result<String> {
+    let dest = PakPath::new(dest);
+    if mount_point.as_str().is_empty() {
+        return Ok(dest.as_str().to_owned());
+    }
+    let prefix = format!("{}/", mount_point.folded());
+    let relative = match dest.folded().starts_with(&prefix) {
+        true => dest.as_str().get(mount_point.as_str().len() + 1..),
+        false => None,
+    };
+    match relative {
+        Some(relative) => Ok(relative.to_owned()),
+        None => Err(Error::PrefixMismatch {
+            prefix: mount_point.to_string(),
+            path: dest.to_string(),
+        }),
+    }
+}
+
+/// Splits a line into whitespace separated tokens, which may be quoted. Each token is returned
+/// with whether it was quoted.
+fn tokenize(line: &str) -> Result<Vec<(bool, String)>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let token: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            tokens.push((true, token));
+        } else {
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+            tokens.push((false, token));
+        }
+    }
+    if line.matches('"').count() % 2 != 0 {
+        return Err(format!("unterminated quote in {line:?}"));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse_response(concat!(
+            "\"C:\\Cooked\\SB\\Content\\a.uasset\" \"../../../SB/Content/a.uasset\" -compress\n",
+            "\n",
+            "\"C:\\Cooked\\SB\\Content\\b c.uasset\" \"../../../SB/Content/b c.uasset\" -encrypt -rehydrate\n",
+            "C:\\Cooked\\SB\\Config\\c.ini ../../../SB/Config/c.ini\n",
+        ))
+        .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].dest, "../../../SB/Content/a.uasset");
+        assert!(entries[0].compress && !entries[0].encrypt);
+        assert_eq!(
+            entries[1].source,
+            PathBuf::from("C:\\Cooked\\SB\\Content\\b c.uasset")
+        );
+        assert!(!entries[1].compress && entries[1].encrypt);
+        assert_eq!(entries[2].dest, "../../../SB/Config/c.ini");
+        assert_eq!(mount_point(&entries), "../../../SB/");
+        assert_eq!(mount_point(&entries[..2]), "../../../SB/Content/");
+
+        assert!(matches!(
+            parse_response("\"a\" \"b\" \"c\""),
+            Err(Error::FileList { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_response("\n\"a \"b\""),
+            Err(Error::FileList { line: 2, .. })
+        ));
+
+        let order = parse_order(concat!(
+            "\"../../../SB/Config/c.ini\" 1\n",
+            "\"../../../SB/Content/A.uasset\" 2\n",
+        ))
+        .unwrap();
+        let mut ordered = entries.clone();
+        apply_order(&mut ordered, &order);
+        assert_eq!(
+            ordered.iter().map(|e| e.dest.as_str()).collect::<Vec<_>>(),
+            vec![
+                "../../../SB/Config/c.ini",
+                "../../../SB/Content/a.uasset",
+                "../../../SB/Content/b c.uasset",
+            ]
+        );
+        assert!(parse_order("\"a\" x").is_err());
+    }
+}
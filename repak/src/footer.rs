@@ -3,9 +3,8 @@ use crate::{
     Hash,
 };
 
-use super::{ext::ReadExt, Compression, Version, VersionMajor};
+use super::{ext::ReadExt, Codecs, Compression, Version, VersionMajor};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
-use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct Footer {
@@ -19,10 +18,17 @@ pub struct Footer {
     pub hash: Hash,
     pub frozen: bool,
     pub compression: Vec<Option<Compression>>,
+    /// Compression names as spelled in the footer, written back in place of the canonical name
+    /// of each slot they still match
+    pub compression_names: Vec<String>,
 }
 
 impl Footer {
-    pub fn read<R: std::io::Read>(reader: &mut R, version: Version) -> Result<Self, super::Error> {
+    pub fn read<R: std::io::Read>(
+        reader: &mut R,
+        version: Version,
+        codecs: &Codecs,
+    ) -> Result<Self, super::Error> {
         let encryption_uuid = (version.version_major() >= VersionMajor::EncryptionKeyGuid)
             .then_try(|| reader.read_u128::<LE>())?;
         let encrypted =
@@ -34,6 +40,7 @@ impl Footer {
         let index_size = reader.read_u64::<LE>()?;
         let hash = Hash(reader.read_guid()?);
         let frozen = version.version_major() == VersionMajor::FrozenIndex && reader.read_bool()?;
+        let mut compression_names = vec![];
         let compression = {
             let mut compression = Vec::with_capacity(match version {
                 ver if ver < Version::V8A => 0,
@@ -41,17 +48,15 @@ impl Footer {
                 _ => 5,
             });
             for _ in 0..compression.capacity() {
-                compression.push(
-                    Compression::from_str(
-                        &reader
-                            .read_len(32)?
-                            .iter()
-                            // filter out whitespace and convert to char
-                            .filter_map(|&ch| (ch != 0).then_some(ch as char))
-                            .collect::<String>(),
-                    )
-                    .ok(),
-                )
+                let name = reader
+                    .read_len(32)?
+                    .iter()
+                    // names are null terminated, keep every byte as a char
+                    .take_while(|&&ch| ch != 0)
+                    .map(|&ch| ch as char)
+                    .collect::<String>();
+                compression.push(codecs.resolve(&name));
+                compression_names.push(name);
             }
             if version.version_major() < VersionMajor::FNameBasedCompression {
                 compression.push(Some(Compression::Zlib));
@@ -80,12 +85,13 @@ impl Footer {
             hash,
             frozen,
             compression,
+            compression_names,
         })
     }
 
     pub fn write<W: std::io::Write>(&self, writer: &mut W) -> Result<(), super::Error> {
         if self.version_major >= VersionMajor::EncryptionKeyGuid {
-            writer.write_u128::<LE>(0)?;
+            writer.write_u128::<LE>(self.encryption_uuid.unwrap_or_default())?;
         }
         if self.version_major >= VersionMajor::IndexEncryption {
             writer.write_bool(self.encrypted)?;
@@ -103,16 +109,100 @@ impl Footer {
             ver if ver < Version::V8B => 4,
             _ => 5,
         };
-        // TODO: handle if compression.len() > algo_size
+        // slots prior to FNameBasedCompression are implied and not written
+        if algo_size > 0 {
+            let count = self
+                .compression
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |i| i + 1);
+            if count > algo_size {
+                return Err(super::Error::TooManyCompressionSlots {
+                    version: self.version,
+                    count,
+                    max: algo_size,
+                });
+            }
+        }
         for i in 0..algo_size {
             let mut name = [0; 32];
             if let Some(algo) = self.compression.get(i).cloned().flatten() {
-                for (i, b) in algo.to_string().as_bytes().iter().enumerate() {
-                    name[i] = *b;
-                }
+                let canonical = algo.to_string();
+                let spelling = self
+                    .compression_names
+                    .get(i)
+                    .filter(|name| name.eq_ignore_ascii_case(&canonical))
+                    .unwrap_or(&canonical);
+                // inverse of reading, so unknown names are written back byte for byte
+                let bytes = spelling
+                    .chars()
+                    .map(|ch| u8::try_from(ch).ok().filter(|&ch| ch != 0))
+                    .collect::<Option<Vec<u8>>>()
+                    .filter(|bytes| bytes.len() <= name.len())
+                    .ok_or_else(|| {
+                        super::Error::Other(format!(
+                            "compression name {algo:?} is not a valid 32 byte name"
+                        ))
+                    })?;
+                name[..bytes.len()].copy_from_slice(&bytes);
             }
             writer.write_all(&name)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn footer(version: Version, compression: Vec<Option<Compression>>) -> Footer {
+        Footer {
+            encryption_uuid: None,
+            encrypted: false,
+            magic: crate::MAGIC,
+            version,
+            version_major: version.version_major(),
+            index_offset: 0,
+            index_size: 0,
+            hash: Hash::default(),
+            frozen: false,
+            compression,
+            compression_names: vec![],
+        }
+    }
+
+    #[test]
+    fn test_compression_names() {
+        let compression = vec![
+            Some(Compression::Oodle),
+            Some(Compression::Unknown("LZMA\u{e9}".to_owned())),
+            None,
+            Some(Compression::Zlib),
+            None,
+        ];
+        let mut buf = vec![];
+        footer(Version::V11, compression.clone())
+            .write(&mut buf)
+            .unwrap();
+        assert_eq!(&buf[buf.len() - 128..buf.len() - 123], b"LZMA\xe9");
+        let read = Footer::read(&mut &buf[..], Version::V11, &Codecs::default()).unwrap();
+        assert_eq!(read.compression, compression);
+
+        // built-in names are matched ignoring case and written back as spelled
+        let len = buf.len();
+        buf[len - 160..len - 155].copy_from_slice(b"oodle");
+        buf[len - 64..len - 60].copy_from_slice(b"ZLIB");
+        let read = Footer::read(&mut &buf[..], Version::V11, &Codecs::default()).unwrap();
+        assert_eq!(read.compression, compression);
+        let mut rewritten = vec![];
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, buf);
+
+        let too_many = vec![Some(Compression::Zlib); 5];
+        assert!(matches!(
+            footer(Version::V8A, too_many).write(&mut vec![]),
+            Err(crate::Error::TooManyCompressionSlots { max: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+/// Matches `path` against a glob `pattern`.
+///
+/// `?` matches any single character and `*` any run of characters, neither crossing a `/`.
+/// `**` matches any run of characters including `/`, and `**/` also matches nothing so that
+/// `a/**/b` matches `a/b`.
+pub(crate) fn matches(pattern: &str, path: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn matches_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            matches_bytes(rest, path)
+                || (0..path.len())
+                    .filter(|&i| path[i] == b'/')
+                    .any(|i| matches_bytes(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches_bytes(rest, &path[i..])),
+        [b'*', rest @ ..] => {
+            let end = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+            (0..=end).any(|i| matches_bytes(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => matches_bytes(rest, path),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path @ ..] if c == p => matches_bytes(rest, path),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::matches;
+
+    #[test]
+    fn test_glob() {
+        assert!(matches("*.uasset", "a.uasset"));
+        assert!(!matches("*.uasset", "dir/a.uasset"));
+        assert!(matches("**.uasset", "dir/a.uasset"));
+        assert!(matches("**/*.uasset", "a.uasset"));
+        assert!(matches("**/*.uasset", "dir/sub/a.uasset"));
+        assert!(matches("SB/**/Art/*", "SB/Content/Art/a.uexp"));
+        assert!(matches("SB/**/Art/*", "SB/Art/a.uexp"));
+        assert!(!matches("SB/**/Art/*", "SB/Content/Art/dir/a.uexp"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+        assert!(matches("*", ""));
+        assert!(!matches("a", "ab"));
+    }
+}
@@ -0,0 +1,575 @@
+//! IoStore containers, consisting of a table of contents (`.utoc`) and the chunk data it
+//! describes (`.ucas`).
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use byteorder::{ReadBytesExt, BE, LE};
+
+use crate::{encryption::Builtin, ext::ReadExt, Codecs, Compression, EncryptionScheme, Error, Key};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+pub const TOC_MAGIC: [u8; 16] = *b"-==--==--==--==-";
+
+#[repr(u8)]
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Debug, strum::Display, strum::FromRepr, strum::EnumIter,
+)]
+pub enum TocVersion {
+    Initial = 1,
+    DirectoryIndex,
+    PartitionSize,
+    PerfectHash,
+    PerfectHashWithOverflow,
+    OnDemandMetaData,
+    RemovedOnDemandMetaData,
+    ReplaceIoChunkHashWithIoHash,
+}
+
+/// Flags of a container, see [`IoStoreReader::flags`]
+pub mod flags {
+    pub const COMPRESSED: u8 = 1 << 0;
+    pub const ENCRYPTED: u8 = 1 << 1;
+    pub const SIGNED: u8 = 1 << 2;
+    pub const INDEXED: u8 = 1 << 3;
+    pub const ON_DEMAND: u8 = 1 << 4;
+}
+
+/// Identifier of a chunk. The meaning of `chunk_type` depends on the engine version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkId {
+    pub id: u64,
+    pub index: u16,
+    pub chunk_type: u8,
+}
+
+impl ChunkId {
+    fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let id = reader.read_u64::<LE>()?;
+        let index = reader.read_u16::<BE>()?;
+        reader.read_u8()?; // padding
+        let chunk_type = reader.read_u8()?;
+        Ok(Self {
+            id,
+            index,
+            chunk_type,
+        })
+    }
+}
+
+/// A chunk and its location in the uncompressed address space of the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    pub id: ChunkId,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionBlock {
+    /// Offset of the stored block in the `.ucas`
+    pub offset: u64,
+    pub compressed: u32,
+    pub uncompressed: u32,
+    /// Index into the compression methods, 0 meaning uncompressed
+    pub method: u8,
+}
+
+impl CompressionBlock {
+    fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut bytes = [0; 12];
+        reader.read_exact(&mut bytes)?;
+        let uint = |range: std::ops::Range<usize>| {
+            bytes[range]
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &b| acc << 8 | b as u64)
+        };
+        Ok(Self {
+            offset: uint(0..5),
+            compressed: uint(5..8) as u32,
+            uncompressed: uint(8..11) as u32,
+            method: bytes[11],
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IoStoreReader {
+    version: TocVersion,
+    container_id: u64,
+    encryption_guid: u128,
+    flags: u8,
+    compression_block_size: u32,
+    partition_size: u64,
+    chunks: Vec<Chunk>,
+    blocks: Vec<CompressionBlock>,
+    compression: Vec<Option<Compression>>,
+    mount_point: Option<String>,
+    files: BTreeMap<String, u32>,
+    key: Key,
+    codecs: Codecs,
+    encryption: Arc<dyn EncryptionScheme>,
+}
+
+impl IoStoreReader {
+    /// Reads the table of contents of an unencrypted container
+    pub fn new<R: Read + Seek>(utoc: &mut R) -> Result<Self> {
+        Self::new_inner(
+            utoc,
+            Key::None,
+            Codecs::default(),
+            Builtin::IOSTORE_DEFAULT.scheme(),
+        )
+    }
+
+    pub(crate) fn new_inner<R: Read + Seek>(
+        utoc: &mut R,
+        key: Key,
+        codecs: Codecs,
+        encryption: Arc<dyn EncryptionScheme>,
+    ) -> Result<Self> {
+        let mut magic = [0; 16];
+        utoc.read_exact(&mut magic)?;
+        if magic != TOC_MAGIC {
+            return Err(Error::TocMagic(magic));
+        }
+        let version = utoc.read_u8()?;
+        let version = TocVersion::from_repr(version).ok_or(Error::TocVersion(version))?;
+        utoc.read_u8()?;
+        utoc.read_u16::<LE>()?;
+        let header_size = utoc.read_u32::<LE>()?;
+        let entry_count = utoc.read_u32::<LE>()?;
+        let block_count = utoc.read_u32::<LE>()?;
+        let block_entry_size = utoc.read_u32::<LE>()?;
+        let method_count = utoc.read_u32::<LE>()?;
+        let method_length = utoc.read_u32::<LE>()?;
+        let compression_block_size = utoc.read_u32::<LE>()?;
+        let directory_index_size = utoc.read_u32::<LE>()?;
+        utoc.read_u32::<LE>()?; // partition count
+        let container_id = utoc.read_u64::<LE>()?;
+        let encryption_guid = utoc.read_u128::<LE>()?;
+        let flags = utoc.read_u8()?;
+        utoc.read_u8()?;
+        utoc.read_u16::<LE>()?;
+        let perfect_hash_seed_count = utoc.read_u32::<LE>()?;
+        let partition_size = utoc.read_u64::<LE>()?;
+        let without_perfect_hash_count = utoc.read_u32::<LE>()?;
+
+        if block_entry_size != 12 {
+            return Err(Error::Other(format!(
+                "unexpected compression block entry size {block_entry_size}"
+            )));
+        }
+        if compression_block_size == 0 {
+            return Err(Error::Other("compression block size is 0".to_owned()));
+        }
+
+        utoc.seek(SeekFrom::Start(header_size as u64))?;
+        let ids = utoc.read_array_len(entry_count as usize, ChunkId::read)?;
+        let chunks = utoc.read_array_len(entry_count as usize, |r| {
+            let mut bytes = [0; 10];
+            r.read_exact(&mut bytes)?;
+            let uint = |bytes: &[u8]| bytes.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+            Ok((uint(&bytes[..5]), uint(&bytes[5..])))
+        })?;
+        let chunks = ids
+            .into_iter()
+            .zip(chunks)
+            .map(|(id, (offset, length))| Chunk { id, offset, length })
+            .collect();
+
+        if version >= TocVersion::PerfectHash {
+            utoc.seek(SeekFrom::Current(perfect_hash_seed_count as i64 * 4))?;
+        }
+        if version >= TocVersion::PerfectHashWithOverflow {
+            utoc.seek(SeekFrom::Current(without_perfect_hash_count as i64 * 4))?;
+        }
+        let blocks = utoc.read_array_len(block_count as usize, CompressionBlock::read)?;
+        let compression = utoc.read_array_len(method_count as usize, |r| {
+            let name = r.read_len(method_length as usize)?;
+            let name = name
+                .iter()
+                .take_while(|&&ch| ch != 0)
+                .map(|&ch| ch as char)
+                .collect::<String>();
+            Ok(codecs.resolve(&name))
+        })?;
+
+        if flags & flags::SIGNED != 0 {
+            let hash_size = utoc.read_u32::<LE>()? as i64;
+            utoc.seek(SeekFrom::Current(hash_size * 2 + 20 * block_count as i64))?;
+        }
+
+        let mut reader = Self {
+            version,
+            container_id,
+            encryption_guid,
+            flags,
+            compression_block_size,
+            partition_size: match partition_size {
+                0 => u64::MAX,
+                size => size,
+            },
+            chunks,
+            blocks,
+            compression,
+            mount_point: None,
+            files: BTreeMap::new(),
+            key,
+            codecs,
+            encryption,
+        };
+
+        if flags & flags::INDEXED != 0 && directory_index_size > 0 {
+            #[allow(unused_mut)]
+            let mut index = utoc.read_len(directory_index_size as usize)?;
+            if flags & flags::ENCRYPTED != 0 {
+                #[cfg(not(feature = "encryption"))]
+                return Err(Error::Encryption);
+                #[cfg(feature = "encryption")]
+                crate::data::decrypt_index(reader.encryption.as_ref(), &reader.key, &mut index)?;
+            }
+            reader.read_directory_index(&mut io::Cursor::new(index))?;
+        }
+        Ok(reader)
+    }
+
+    fn read_directory_index<R: Read>(&mut self, index: &mut R) -> Result<()> {
+        const NONE: u32 = u32::MAX;
+
+        let mount_point = index.read_string()?;
+        let directories = ReadExt::read_array(index, |r| {
+            Ok([
+                r.read_u32::<LE>()?, // name
+                r.read_u32::<LE>()?, // first child
+                r.read_u32::<LE>()?, // next sibling
+                r.read_u32::<LE>()?, // first file
+            ])
+        })?;
+        let files = ReadExt::read_array(index, |r| {
+            Ok([
+                r.read_u32::<LE>()?, // name
+                r.read_u32::<LE>()?, // next file
+                r.read_u32::<LE>()?, // chunk index
+            ])
+        })?;
+        let strings = ReadExt::read_array(index, |r| r.read_string())?;
+
+        let invalid = || Error::Other("invalid utoc directory index".to_owned());
+        let name = |i: u32| strings.get(i as usize).ok_or_else(invalid);
+
+        // bound the walk so malformed links cannot cause an endless loop
+        let mut budget = directories.len() + files.len();
+        let mut stack = vec![(0, String::new())];
+        while let Some((dir, path)) = stack.pop() {
+            if directories.is_empty() {
+                break;
+            }
+            let [_, first_child, _, first_file] = *directories.get(dir).ok_or_else(invalid)?;
+            let mut file = first_file;
+            while file != NONE {
+                budget = budget.checked_sub(1).ok_or_else(invalid)?;
+                let [file_name, next, chunk] = *files.get(file as usize).ok_or_else(invalid)?;
+                self.files
+                    .insert(format!("{path}{}", name(file_name)?), chunk);
+                file = next;
+            }
+            let mut child = first_child;
+            while child != NONE {
+                budget = budget.checked_sub(1).ok_or_else(invalid)?;
+                let [dir_name, _, next, _] =
+                    *directories.get(child as usize).ok_or_else(invalid)?;
+                stack.push((child as usize, format!("{path}{}/", name(dir_name)?)));
+                child = next;
+            }
+        }
+        self.mount_point = Some(mount_point);
+        Ok(())
+    }
+
+    pub fn version(&self) -> TocVersion {
+        self.version
+    }
+
+    pub fn container_id(&self) -> u64 {
+        self.container_id
+    }
+
+    pub fn encryption_guid(&self) -> u128 {
+        self.encryption_guid
+    }
+
+    /// Container flags, see [`flags`]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn compression_block_size(&self) -> u32 {
+        self.compression_block_size
+    }
+
+    pub fn compression(&self) -> &[Option<Compression>] {
+        &self.compression
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    pub fn blocks(&self) -> &[CompressionBlock] {
+        &self.blocks
+    }
+
+    /// Mount point of the directory index, if the container is indexed
+    pub fn mount_point(&self) -> Option<&str> {
+        self.mount_point.as_deref()
+    }
+
+    /// Paths of the directory index relative to the mount point and their chunks
+    pub fn files(&self) -> impl Iterator<Item = (&str, &Chunk)> {
+        self.files.iter().filter_map(|(path, &chunk)| {
+            self.chunks
+                .get(chunk as usize)
+                .map(|chunk| (path.as_str(), chunk))
+        })
+    }
+
+    pub fn get_chunk(&self, path: &str) -> Option<&Chunk> {
+        self.files
+            .get(path)
+            .and_then(|&chunk| self.chunks.get(chunk as usize))
+    }
+
+    /// Compression blocks holding the data of `chunk`
+    pub fn chunk_blocks(&self, chunk: &Chunk) -> &[CompressionBlock] {
+        if chunk.length == 0 {
+            return &[];
+        }
+        let block_size = self.compression_block_size as u64;
+        let first = (chunk.offset / block_size) as usize;
+        let last = ((chunk.offset + chunk.length - 1) / block_size) as usize;
+        &self.blocks[first.min(self.blocks.len())..(last + 1).min(self.blocks.len())]
+    }
+
+    /// Reads the data of `chunk` from the `.ucas` of the container. Only the first partition is
+    /// supported.
+    pub fn read_chunk<R: Read + Seek, W: Write>(
+        &self,
+        chunk: &Chunk,
+        ucas: &mut R,
+        writer: &mut W,
+    ) -> Result<()> {
+        let blocks = self.chunk_blocks(chunk);
+        let mut skip = (chunk.offset % self.compression_block_size as u64) as usize;
+        let mut remaining = chunk.length as usize;
+        for block in blocks {
+            let data = self.read_block(block, ucas)?;
+            let data = data.get(skip..).unwrap_or_default();
+            let data = &data[..remaining.min(data.len())];
+            writer.write_all(data)?;
+            remaining -= data.len();
+            skip = 0;
+        }
+        if remaining > 0 {
+            return Err(Error::Other(format!(
+                "chunk {:?} extends past the compression blocks",
+                chunk.id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads the data of the chunk at `path`
+    pub fn get<R: Read + Seek>(&self, path: &str, ucas: &mut R) -> Result<Vec<u8>> {
+        let chunk = self
+            .get_chunk(path)
+            .ok_or_else(|| Error::MissingEntry(path.to_owned()))?;
+        let mut data = Vec::with_capacity(chunk.length as usize);
+        self.read_chunk(chunk, ucas, &mut data)?;
+        Ok(data)
+    }
+
+    fn read_block<R: Read + Seek>(
+        &self,
+        block: &CompressionBlock,
+        ucas: &mut R,
+    ) -> Result<Vec<u8>> {
+        if block.offset / self.partition_size != 0 {
+            return Err(Error::Other(format!(
+                "block at {:#x} is outside the first partition",
+                block.offset
+            )));
+        }
+        ucas.seek(SeekFrom::Start(block.offset))?;
+        let encrypted = self.flags & flags::ENCRYPTED != 0;
+        #[allow(unused_mut)]
+        let mut data = ucas.read_len(match encrypted {
+            true => (block.compressed as usize + 15) & !15,
+            false => block.compressed as usize,
+        })?;
+        if encrypted {
+            #[cfg(not(feature = "encryption"))]
+            return Err(Error::Encryption);
+            #[cfg(feature = "encryption")]
+            {
+                crate::data::decrypt(self.encryption.as_ref(), &self.key, &mut data)?;
+                data.truncate(block.compressed as usize);
+            }
+        }
+        match block.method {
+            0 => Ok(data),
+            method => {
+                let compression = self
+                    .compression
+                    .get(method as usize - 1)
+                    .and_then(Option::as_ref)
+                    .ok_or_else(|| Error::Other(format!("unknown compression method {method}")))?;
+                let mut out = vec![0; block.uncompressed as usize];
+                self.codecs
+                    .codec(compression)?
+                    .decompress(&data, &mut out)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ext::WriteExt;
+    use byteorder::WriteBytesExt;
+
+    /// Builds an unencrypted, zlib compressed container holding `files`
+    fn build(files: &[(&str, &[u8])], block_size: u32) -> (Vec<u8>, Vec<u8>) {
+        let mut ucas = vec![];
+        let mut blocks = vec![];
+        let mut chunks = vec![];
+        let mut offset = 0;
+        for (i, (_, data)) in files.iter().enumerate() {
+            chunks.push((i as u64, offset, data.len() as u64));
+            // chunks start at block boundaries
+            for block in data.chunks(block_size as usize) {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::fast());
+                encoder.write_all(block).unwrap();
+                let compressed = encoder.finish().unwrap();
+                blocks.push((ucas.len() as u64, compressed.len(), block.len()));
+                ucas.extend(compressed);
+            }
+            offset += (data.len() as u64).div_ceil(block_size as u64) * block_size as u64;
+        }
+
+        // root directory containing `Content/` which holds all files
+        let mut index = vec![];
+        index.write_string("../../../SB/").unwrap();
+        index.write_u32::<LE>(2).unwrap();
+        for dir in [
+            [u32::MAX, 1, u32::MAX, u32::MAX],
+            [0, u32::MAX, u32::MAX, 0],
+        ] {
+            for value in dir {
+                index.write_u32::<LE>(value).unwrap();
+            }
+        }
+        index.write_u32::<LE>(files.len() as u32).unwrap();
+        for i in 0..files.len() as u32 {
+            let next = match i + 1 < files.len() as u32 {
+                true => i + 1,
+                false => u32::MAX,
+            };
+            for value in [i + 1, next, i] {
+                index.write_u32::<LE>(value).unwrap();
+            }
+        }
+        index.write_u32::<LE>(files.len() as u32 + 1).unwrap();
+        index.write_string("Content").unwrap();
+        for (name, _) in files {
+            index.write_string(name).unwrap();
+        }
+
+        let mut utoc = vec![];
+        utoc.write_all(&TOC_MAGIC).unwrap();
+        utoc.write_u8(TocVersion::PartitionSize as u8).unwrap();
+        utoc.write_all(&[0; 3]).unwrap();
+        for value in [
+            144,
+            files.len() as u32,
+            blocks.len() as u32,
+            12,
+            1,
+            32,
+            block_size,
+            index.len() as u32,
+            1,
+        ] {
+            utoc.write_u32::<LE>(value).unwrap();
+        }
+        utoc.write_u64::<LE>(0x1234).unwrap();
+        utoc.write_u128::<LE>(0).unwrap();
+        utoc.write_u8(flags::COMPRESSED | flags::INDEXED).unwrap();
+        utoc.write_all(&[0; 7]).unwrap();
+        utoc.write_u64::<LE>(u64::MAX).unwrap();
+        utoc.write_all(&[0; 48]).unwrap();
+        assert_eq!(utoc.len(), 144);
+
+        for (id, _, _) in &chunks {
+            utoc.write_u64::<LE>(*id).unwrap();
+            utoc.write_all(&[0, 0, 0, 2]).unwrap();
+        }
+        for (_, offset, length) in &chunks {
+            utoc.write_all(&offset.to_be_bytes()[3..]).unwrap();
+            utoc.write_all(&length.to_be_bytes()[3..]).unwrap();
+        }
+        for (offset, compressed, uncompressed) in &blocks {
+            utoc.write_all(&offset.to_le_bytes()[..5]).unwrap();
+            utoc.write_all(&compressed.to_le_bytes()[..3]).unwrap();
+            utoc.write_all(&uncompressed.to_le_bytes()[..3]).unwrap();
+            utoc.write_u8(1).unwrap();
+        }
+        let mut name = [0; 32];
+        name[..4].copy_from_slice(b"Zlib");
+        utoc.write_all(&name).unwrap();
+        utoc.write_all(&index).unwrap();
+        (utoc, ucas)
+    }
+
+    #[test]
+    fn test_read() {
+        let large: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let files: &[(&str, &[u8])] = &[
+            ("a.uasset", b"package"),
+            ("a.ubulk", &large),
+            ("b.uasset", b"other package"),
+        ];
+        let (utoc, ucas) = build(files, 0x100);
+
+        let toc = IoStoreReader::new(&mut io::Cursor::new(utoc)).unwrap();
+        assert_eq!(toc.version(), TocVersion::PartitionSize);
+        assert_eq!(toc.container_id(), 0x1234);
+        assert_eq!(toc.compression(), [Some(Compression::Zlib)]);
+        assert_eq!(toc.mount_point(), Some("../../../SB/"));
+        assert_eq!(
+            toc.files().map(|(path, _)| path).collect::<Vec<_>>(),
+            ["Content/a.uasset", "Content/a.ubulk", "Content/b.uasset"]
+        );
+        assert_eq!(
+            toc.chunk_blocks(toc.get_chunk("Content/a.ubulk").unwrap())
+                .len(),
+            4
+        );
+
+        let mut ucas = io::Cursor::new(ucas);
+        for (name, data) in files {
+            let path = format!("Content/{name}");
+            assert_eq!(&toc.get(&path, &mut ucas).unwrap(), data);
+        }
+        assert!(matches!(
+            IoStoreReader::new(&mut io::Cursor::new(vec![0; 144])),
+            Err(Error::TocMagic(_))
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+//! Extraction of the AES key of a pak from the game executable.
+//!
+//! Shipping builds set up the key in the `GetPakEncryptionKey` callback as eight 32 bit
+//! immediates stored to consecutive offsets of the output buffer, e.g.
+//!
+//! ```text
+//! C7 01 xx xx xx xx       mov dword ptr [rcx], imm32
+//! C7 41 04 xx xx xx xx    mov dword ptr [rcx+4], imm32
+//! ...
+//! C7 41 1C xx xx xx xx    mov dword ptr [rcx+1Ch], imm32
+//! ```
+//!
+//! Every such sequence is a candidate, which is verified by decrypting the index of a pak and
+//! checking that it starts with a plausible mount point.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{encryption::Builtin, ext::ReadExt, footer::Footer, Codecs, Error, Version};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Potential key found in an executable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Offset of the first instruction of the key setup
+    pub offset: usize,
+    pub key: [u8; 32],
+}
+
+/// Key which decrypts the index of the pak passed to [`scan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundKey {
+    pub offset: usize,
+    pub key: [u8; 32],
+    pub scheme: Builtin,
+    /// Mount point read from the decrypted index
+    pub mount_point: String,
+}
+
+impl FoundKey {
+    pub fn aes(&self) -> aes::Aes256 {
+        use aes::cipher::KeyInit;
+        aes::Aes256::new_from_slice(&self.key).unwrap()
+    }
+}
+
+/// Finds all key setup sequences in `exe`
+pub fn candidates(exe: &[u8]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = vec![];
+    let mut pos = 0;
+    while pos < exe.len() {
+        match key_setup(&exe[pos..]) {
+            Some((key, len)) => {
+                if !candidates.iter().any(|c| c.key == key) {
+                    candidates.push(Candidate { offset: pos, key });
+                }
+                pos += len;
+            }
+            None => pos += 1,
+        }
+    }
+    candidates
+}
+
+/// Finds the keys in `exe` which decrypt the index of `pak`, trying each built-in scheme
+pub fn scan<R: Read + Seek>(exe: &[u8], pak: &mut R) -> Result<Vec<FoundKey>> {
+    let index = encrypted_index(pak)?;
+    let mut found = vec![];
+    for candidate in candidates(exe) {
+        for scheme in [Builtin::UnrealStandard, Builtin::StellarBladePartial] {
+            let key = FoundKey {
+                offset: candidate.offset,
+                key: candidate.key,
+                scheme,
+                mount_point: String::new(),
+            };
+            let mut index = index.clone();
+            scheme.scheme().decrypt_index(&key.aes(), &mut index);
+            if let Some(mount_point) = mount_point(&index) {
+                found.push(FoundKey { mount_point, ..key });
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Decodes a sequence of eight `mov dword ptr [reg+disp8], imm32` covering displacements 0 to
+/// 0x1C on one base register in any order, returning the key and the length of the sequence
+fn key_setup(code: &[u8]) -> Option<([u8; 32], usize)> {
+    let mut key = [0; 32];
+    let mut seen = 0u8;
+    let mut base = None;
+    let mut pos = 0;
+    for _ in 0..8 {
+        let (register, disp, imm, len) = mov_imm32(code.get(pos..)?)?;
+        if *base.get_or_insert(register) != register || disp % 4 != 0 || disp >= 32 {
+            return None;
+        }
+        let slot = disp / 4;
+        if seen & (1 << slot) != 0 {
+            return None;
+        }
+        seen |= 1 << slot;
+        key[disp as usize..disp as usize + 4].copy_from_slice(&imm);
+        pos += len;
+    }
+    // a run of identical immediates is more likely initialization than a key
+    (key.chunks(4).any(|c| c != &key[..4])).then_some((key, pos))
+}
+
+/// Decodes `mov dword ptr [reg+disp8], imm32` with an optional REX prefix
+fn mov_imm32(code: &[u8]) -> Option<(u8, u8, [u8; 4], usize)> {
+    let (rex, code, prefix) = match code.first()? {
+        // REX.B only, REX.W would make it a 64 bit store
+        0x41 => (8, &code[1..], 1),
+        _ => (0, code, 0),
+    };
+    let [0xC7, modrm, ..] = *code else {
+        return None;
+    };
+    let register = modrm & 7;
+    // opcode extension must be 0 and rsp/r12 based addressing needs a SIB byte
+    if modrm & 0x38 != 0 || register == 4 {
+        return None;
+    }
+    let (disp, rest) = match modrm >> 6 {
+        0 if register != 5 => (0, code.get(2..)?),
+        1 => (*code.get(2)?, code.get(3..)?),
+        _ => return None,
+    };
+    let imm = rest.get(..4)?.try_into().unwrap();
+    Some((
+        register | rex,
+        disp,
+        imm,
+        prefix + code.len() - rest.len() + 4,
+    ))
+}
+
+/// Reads the leading bytes of the encrypted index of `pak`, enough to hold a mount point
+fn encrypted_index<R: Read + Seek>(pak: &mut R) -> Result<Vec<u8>> {
+    let len = pak.seek(SeekFrom::End(0))?;
+    for version in Version::iter() {
+        if len < version.size() as u64 {
+            continue;
+        }
+        pak.seek(SeekFrom::End(-version.size()))?;
+        let Ok(footer) = Footer::read(pak, version, &Codecs::default()) else {
+            continue;
+        };
+        if !footer.encrypted {
+            return Err(Error::Other("pak index is not encrypted".to_owned()));
+        }
+        pak.seek(SeekFrom::Start(footer.index_offset))?;
+        return pak.read_len(footer.index_size.min(0x1000) as usize & !15);
+    }
+    Err(Error::UnsupportedOrEncrypted(String::new()))
+}
+
+/// Parses the FString at the start of a decrypted index if it is a printable, null terminated
+/// string
+fn mount_point(index: &[u8]) -> Option<String> {
+    let len = i32::from_le_bytes(index.get(..4)?.try_into().unwrap());
+    let chars: Vec<u16> = match len {
+        1.. => index
+            .get(4..4 + len as usize)?
+            .iter()
+            .map(|&b| b as u16)
+            .collect(),
+        ..=-1 => index
+            .get(4..4 + 2 * len.unsigned_abs() as usize)?
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect(),
+        0 => return None,
+    };
+    let (0, chars) = chars.split_last()? else {
+        return None;
+    };
+    chars
+        .iter()
+        .all(|&c| (0x20..0x7f).contains(&c))
+        .then(|| String::from_utf16_lossy(chars))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup(key: &[u8; 32], order: &[usize], rex: bool) -> Vec<u8> {
+        let mut code = vec![];
+        for &i in order {
+            if rex {
+                code.push(0x41);
+            }
+            match i {
+                0 => code.extend([0xC7, 0x00]),
+                _ => code.extend([0xC7, 0x40, i as u8 * 4]),
+            }
+            code.extend(&key[i * 4..i * 4 + 4]);
+        }
+        code
+    }
+
+    #[test]
+    fn test_candidates() {
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8 * 7 + 1);
+        let mut exe = vec![0xCC; 64];
+        exe.extend(setup(&key, &[0, 1, 2, 3, 4, 5, 6, 7], false));
+        exe.extend([0xC3; 16]);
+        exe.extend(setup(&key, &[7, 6, 5, 4, 3, 2, 1, 0], true));
+        exe.extend(setup(&[0; 32], &[0, 1, 2, 3, 4, 5, 6, 7], false));
+        assert_eq!(candidates(&exe), vec![Candidate { offset: 64, key }]);
+
+        // incomplete or mixed sequences
+        assert!(candidates(&setup(&key, &[0, 1, 2, 3, 4, 5, 6], false)).is_empty());
+        assert!(candidates(&setup(&key, &[0, 1, 2, 3, 4, 5, 6, 6], false)).is_empty());
+
+        let mut index = vec![10, 0, 0, 0];
+        index.extend(b"../../../\0");
+        assert_eq!(mount_point(&index).as_deref(), Some("../../../"));
+        index[5] = 0x01;
+        assert_eq!(mount_point(&index), None);
+    }
+}
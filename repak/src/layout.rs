@@ -0,0 +1,245 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use byteorder::{ReadBytesExt, LE};
+
+use crate::{entry::Entry, ext::ReadExt, footer::Footer, Error, PakReader, VersionMajor};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Inline copy of an entry preceding its data
+    EntryHeader,
+    EntryData,
+    /// Zeroed bytes between other regions
+    Padding,
+    Index,
+    PathHashIndex,
+    FullDirectoryIndex,
+    Footer,
+    /// Non-zero bytes not referenced by anything
+    Unknown,
+}
+
+impl std::fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RegionKind::EntryHeader => "entry header",
+            RegionKind::EntryData => "entry data",
+            RegionKind::Padding => "padding",
+            RegionKind::Index => "index",
+            RegionKind::PathHashIndex => "path hash index",
+            RegionKind::FullDirectoryIndex => "full directory index",
+            RegionKind::Footer => "footer",
+            RegionKind::Unknown => "unknown",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub offset: u64,
+    pub size: u64,
+    /// Path of the entry for entry headers and data
+    pub path: Option<String>,
+}
+
+/// Byte map of a pak file, as produced by [`PakReader::layout`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Non-overlapping regions ordered by offset and covering the whole file
+    pub regions: Vec<Region>,
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for region in &self.regions {
+            write!(
+                f,
+                "{:#012x} {:#012x} {}",
+                region.offset, region.size, region.kind
+            )?;
+            if let Some(path) = &region.path {
+                write!(f, " {path}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decrypted index buffers of a pak, as produced by [`PakReader::index_blobs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexBlobs {
+    pub index: Vec<u8>,
+    pub path_hash_index: Option<Vec<u8>>,
+    pub full_directory_index: Option<Vec<u8>>,
+}
+
+impl IndexBlobs {
+    /// Writes the buffers to `index.bin`, `phi.bin` and `fdi.bin` in `dir`
+    pub fn write_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::write(dir.join("index.bin"), &self.index)?;
+        if let Some(phi) = &self.path_hash_index {
+            std::fs::write(dir.join("phi.bin"), phi)?;
+        }
+        if let Some(fdi) = &self.full_directory_index {
+            std::fs::write(dir.join("fdi.bin"), fdi)?;
+        }
+        Ok(())
+    }
+}
+
+/// Location and decrypted contents of the index, path hash index and full directory index
+struct RawIndex {
+    footer: Footer,
+    index: Vec<u8>,
+    path_hash_index: Option<(u64, Vec<u8>)>,
+    full_directory_index: Option<(u64, Vec<u8>)>,
+}
+
+fn read_raw_index<R: Read + Seek>(pak: &PakReader, reader: &mut R) -> Result<RawIndex> {
+    let version = pak.version();
+    reader.seek(SeekFrom::End(-version.size()))?;
+    let footer = Footer::read(reader, version, pak.codecs())?;
+
+    let read = |reader: &mut R, offset: u64, size: u64| -> Result<Vec<u8>> {
+        reader.seek(SeekFrom::Start(offset))?;
+        #[allow(unused_mut)]
+        let mut buf = reader.read_len(size as usize)?;
+        if footer.encrypted {
+            #[cfg(not(feature = "encryption"))]
+            return Err(Error::Encryption);
+            #[cfg(feature = "encryption")]
+            crate::data::decrypt_index(pak.encryption().as_ref(), pak.key(), &mut buf)?;
+        }
+        Ok(buf)
+    };
+
+    let index = read(reader, footer.index_offset, footer.index_size)?;
+    let mut path_hash_index = None;
+    let mut full_directory_index = None;
+    if version.version_major() >= VersionMajor::PathHashIndex {
+        let mut header = io::Cursor::new(&index);
+        header.read_string()?; // mount point
+        header.read_u32::<LE>()?; // entry count
+        header.read_u64::<LE>()?; // path hash seed
+        for section in [&mut path_hash_index, &mut full_directory_index] {
+            if header.read_u32::<LE>()? != 0 {
+                let offset = header.read_u64::<LE>()?;
+                let size = header.read_u64::<LE>()?;
+                header.read_len(20)?; // hash
+                *section = Some((offset, read(reader, offset, size)?));
+            }
+        }
+    }
+
+    Ok(RawIndex {
+        footer,
+        index,
+        path_hash_index,
+        full_directory_index,
+    })
+}
+
+pub(crate) fn index_blobs<R: Read + Seek>(pak: &PakReader, reader: &mut R) -> Result<IndexBlobs> {
+    let raw = read_raw_index(pak, reader)?;
+    Ok(IndexBlobs {
+        index: raw.index,
+        path_hash_index: raw.path_hash_index.map(|(_, buf)| buf),
+        full_directory_index: raw.full_directory_index.map(|(_, buf)| buf),
+    })
+}
+
+pub(crate) fn layout<R: Read + Seek>(pak: &PakReader, reader: &mut R) -> Result<Layout> {
+    let version = pak.version();
+    let len = reader.seek(SeekFrom::End(0))?;
+    let raw = read_raw_index(pak, reader)?;
+
+    let mut regions = vec![];
+    for (path, entry) in pak.entries() {
+        if entry.is_deleted() {
+            continue;
+        }
+        let block_count = entry.blocks.as_ref().map_or(0, |b| b.len() as u32);
+        let header = Entry::get_serialized_size(version, entry.compression_slot, block_count);
+        regions.push(Region {
+            kind: RegionKind::EntryHeader,
+            offset: entry.offset,
+            size: header,
+            path: Some(path.to_owned()),
+        });
+        regions.push(Region {
+            kind: RegionKind::EntryData,
+            offset: entry.offset + header,
+            size: entry.data_size(),
+            path: Some(path.to_owned()),
+        });
+    }
+    regions.push(Region {
+        kind: RegionKind::Index,
+        offset: raw.footer.index_offset,
+        size: raw.index.len() as u64,
+        path: None,
+    });
+    for (kind, section) in [
+        (RegionKind::PathHashIndex, &raw.path_hash_index),
+        (RegionKind::FullDirectoryIndex, &raw.full_directory_index),
+    ] {
+        if let Some((offset, buf)) = section {
+            regions.push(Region {
+                kind,
+                offset: *offset,
+                size: buf.len() as u64,
+                path: None,
+            });
+        }
+    }
+    regions.push(Region {
+        kind: RegionKind::Footer,
+        offset: len - version.size() as u64,
+        size: version.size() as u64,
+        path: None,
+    });
+    regions.sort_by_key(|region| region.offset);
+
+    // fill the gaps between regions
+    let mut filled = vec![];
+    let mut pos = 0;
+    for region in regions {
+        if region.offset > pos {
+            filled.push(gap(reader, pos, region.offset - pos)?);
+        }
+        pos = pos.max(region.offset + region.size);
+        filled.push(region);
+    }
+    if len > pos {
+        filled.push(gap(reader, pos, len - pos)?);
+    }
+    Ok(Layout { regions: filled })
+}
+
+fn gap<R: Read + Seek>(reader: &mut R, offset: u64, size: u64) -> Result<Region> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut zeroed = true;
+    let mut buf = vec![0; 0x10000];
+    let mut remaining = size;
+    while remaining > 0 && zeroed {
+        let chunk = &mut buf[..remaining.min(0x10000) as usize];
+        reader.read_exact(chunk)?;
+        zeroed = chunk.iter().all(|&b| b == 0);
+        remaining -= chunk.len() as u64;
+    }
+    Ok(Region {
+        kind: match zeroed {
+            true => RegionKind::Padding,
+            false => RegionKind::Unknown,
+        },
+        offset,
+        size,
+        path: None,
+    })
+}
@@ -1,13 +1,42 @@
 #![allow(dead_code)]
+pub mod codec;
+mod compact;
 mod data;
+mod diff;
+pub mod encryption;
 pub mod entry;
 mod error;
 mod ext;
+pub mod filelist;
 mod footer;
+mod glob;
+pub mod iostore;
+#[cfg(feature = "encryption")]
+pub mod keyfinder;
+mod layout;
 mod pak;
+mod patch;
+mod path;
+mod profile;
+mod recover;
+#[cfg(feature = "signing")]
+pub mod sig;
 pub mod utils;
 
-pub use {data::PartialEntry, error::*, pak::*};
+pub use {
+    codec::{Codec, Codecs},
+    compact::compact,
+    data::PartialEntry,
+    diff::*,
+    encryption::{EncryptionScheme, StellarBladePartial, UnrealStandard},
+    error::*,
+    layout::{IndexBlobs, Layout, Region, RegionKind},
+    pak::*,
+    patch::*,
+    path::PakPath,
+    profile::GameProfile,
+    recover::{recover, Recovery},
+};
 
 pub const MAGIC: u32 = 0x5A6F12E1;
 
@@ -24,6 +53,7 @@ pub const MAGIC: u32 = 0x5A6F12E1;
     strum::EnumString,
     strum::VariantNames,
 )]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Version {
     V0,
     V1,
@@ -112,17 +142,34 @@ impl Version {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, strum::Display, strum::EnumString, strum::VariantNames)]
+#[derive(Clone, PartialEq, Eq, Debug, Default, strum::EnumString, strum::VariantNames)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Compression {
     Zlib,
     Gzip,
+    #[default]
     Oodle,
     Zstd,
     LZ4,
+    /// Codec registered with [`PakBuilder::codec`] under this name
+    #[strum(disabled)]
+    Custom(String),
+    /// Name found in a footer which is neither built in nor registered. Kept so the footer can
+    /// be written back unchanged, but entries using it cannot be read.
+    #[strum(disabled)]
+    Unknown(String),
 }
-impl Default for Compression {
-    fn default() -> Self {
-        Compression::Oodle
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Compression::Zlib => "Zlib",
+            Compression::Gzip => "Gzip",
+            Compression::Oodle => "Oodle",
+            Compression::Zstd => "Zstd",
+            Compression::LZ4 => "LZ4",
+            Compression::Custom(name) | Compression::Unknown(name) => name,
+        })
     }
 }
 #[allow(clippy::large_enum_variant)]
@@ -1,12 +1,16 @@
-use crate::data::{build_partial_entry, pad_length};
-use crate::entry::Entry;
-use crate::{Compression, Error, PartialEntry};
+use crate::data::{build_partial_entry, pad_length, COMPRESSION_BLOCK_SIZE};
+use crate::entry::{narrow, Entry};
+use crate::{
+    encryption::Builtin, Codec, Codecs, Compression, EncryptionScheme, Error, GameProfile, PakPath,
+    PartialEntry,
+};
 
 use super::ext::{ReadExt, WriteExt};
 use super::{Version, VersionMajor};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{self, Read, Seek, Write};
+use std::sync::Arc;
 
 #[derive(Default, Clone, Copy, PartialEq)]
 pub struct Hash(pub(crate) [u8; 20]);
@@ -16,10 +20,91 @@ impl std::fmt::Debug for Hash {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Hash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let hex = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        let mut hash = [0; 20];
+        hex::decode_to_slice(hex.as_ref(), &mut hash).map_err(D::Error::custom)?;
+        Ok(Hash(hash))
+    }
+}
+
+/// Complete index metadata of a pak, sufficient to rebuild it given the file contents
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PakManifest {
+    pub version: Version,
+    pub mount_point: String,
+    pub path_hash_seed: Option<u64>,
+    pub encrypted_index: bool,
+    /// Frozen index flag of V9 footers
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frozen_index: bool,
+    /// Whether the V10+ index has a path hash index
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub path_hash_index: bool,
+    /// Whether the V10+ index has a full directory index. Entries of paks without one are named
+    /// `hashed/<path hash>`.
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub full_directory_index: bool,
+    pub encryption_guid: Option<u128>,
+    pub compression: Vec<Option<Compression>>,
+    pub entries: BTreeMap<String, Entry>,
+}
+
+#[cfg(feature = "serde")]
+fn default_true() -> bool {
+    true
+}
+
+/// Selects the entries whose data is encrypted by path
+#[derive(Clone)]
+pub(crate) struct EncryptFilter(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl Default for EncryptFilter {
+    fn default() -> Self {
+        Self(Arc::new(|_| true))
+    }
+}
+
+impl std::fmt::Debug for EncryptFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptFilter")
+    }
+}
+
+impl EncryptFilter {
+    fn key<'k>(&self, key: &'k super::Key, path: &str) -> &'k super::Key {
+        match (self.0)(path) {
+            true => key,
+            false => &super::Key::None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PakBuilder {
     key: super::Key,
     allowed_compression: Vec<Compression>,
+    alignment: u64,
+    frozen_index: bool,
+    path_hash_index: bool,
+    full_directory_index: bool,
+    encrypt_index: bool,
+    encrypt_filter: EncryptFilter,
+    codecs: Codecs,
+    /// Scheme selected with [`PakBuilder::encryption`] or [`PakBuilder::profile`], otherwise the
+    /// default of paks or IoStore containers respectively
+    encryption: Option<Arc<dyn EncryptionScheme>>,
 }
 
 impl Default for PakBuilder {
@@ -33,6 +118,14 @@ impl PakBuilder {
         Self {
             key: Default::default(),
             allowed_compression: Default::default(),
+            alignment: 1,
+            frozen_index: false,
+            path_hash_index: true,
+            full_directory_index: true,
+            encrypt_index: true,
+            encrypt_filter: Default::default(),
+            codecs: Default::default(),
+            encryption: None,
         }
     }
     #[cfg(feature = "encryption")]
@@ -45,15 +138,139 @@ impl PakBuilder {
         self.allowed_compression = compression.into_iter().collect();
         self
     }
+    /// Pads written entries so the data of each starts at a multiple of `alignment` bytes, e.g.
+    /// 0x1000 for memory mapping. 0 and 1 disable padding.
+    pub fn alignment(mut self, alignment: u64) -> Self {
+        self.alignment = alignment.max(1);
+        self
+    }
+    /// Sets the frozen index flag in the footer of written V9 paks. Only the flag is written,
+    /// the index itself keeps the regular layout.
+    pub fn frozen_index(mut self, frozen_index: bool) -> Self {
+        self.frozen_index = frozen_index;
+        self
+    }
+    /// Whether written V10+ paks include a path hash index, on by default. Lookups by path hash
+    /// are all the engine needs unless it also has to enumerate directories.
+    pub fn path_hash_index(mut self, path_hash_index: bool) -> Self {
+        self.path_hash_index = path_hash_index;
+        self
+    }
+    /// Whether written V10+ paks include a full directory index, on by default. Leaving it out
+    /// saves memory at runtime but leaves only path hashes in the pak, so readers can no longer
+    /// list the original file names. At least one of the two indexes must be kept.
+    pub fn full_directory_index(mut self, full_directory_index: bool) -> Self {
+        self.full_directory_index = full_directory_index;
+        self
+    }
+    /// Whether written paks have an encrypted index when a key is set, on by default
+    pub fn encrypt_index(mut self, encrypt_index: bool) -> Self {
+        self.encrypt_index = encrypt_index;
+        self
+    }
+    /// Selects the entries written with [`PakWriter::write_file`] or [`EntryBuilder`] whose data
+    /// is encrypted when a key is set, by their path. All entries are encrypted by default, while
+    /// `|_| false` leaves only the index encrypted.
+    pub fn encrypt_entries(
+        mut self,
+        filter: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.encrypt_filter = EncryptFilter(Arc::new(filter));
+        self
+    }
+    /// Applies the compression and encryption settings of `profile`. Its version, mount point
+    /// and path hash seed are applied by [`PakBuilder::writer_for_profile`].
+    pub fn profile(mut self, profile: &GameProfile) -> Self {
+        self.allowed_compression = profile.compression.clone();
+        self.encryption = Some(profile.encryption.scheme());
+        self
+    }
+    /// Selects how entries and the index are encrypted. Paks default to
+    /// [`Builtin::PAK_DEFAULT`] ([`crate::StellarBladePartial`]) and IoStore containers to
+    /// [`Builtin::IOSTORE_DEFAULT`] ([`crate::UnrealStandard`]), so stock Unreal Engine paks need
+    /// `.encryption(UnrealStandard)`.
+    pub fn encryption(mut self, scheme: impl EncryptionScheme + 'static) -> Self {
+        self.encryption = Some(Arc::new(scheme));
+        self
+    }
+    fn pak_encryption(&self) -> Arc<dyn EncryptionScheme> {
+        self.encryption
+            .clone()
+            .unwrap_or_else(|| Builtin::PAK_DEFAULT.scheme())
+    }
+    /// Registers a compression codec for reading and writing, replacing any codec of the same
+    /// name. Custom codecs are selected for writing by passing `Compression::Custom(name)` to
+    /// [`PakBuilder::compression`].
+    pub fn codec(mut self, codec: impl Codec + 'static) -> Self {
+        self.codecs.register(codec);
+        self
+    }
+    /// Rebuilds a pak from `manifest`, see [`PakWriter::from_manifest`]
+    pub fn writer_from_manifest<W, F>(
+        self,
+        writer: W,
+        manifest: &PakManifest,
+        data_source: F,
+    ) -> Result<PakWriter<W>, super::Error>
+    where
+        W: Write + Seek,
+        F: FnMut(&str) -> Result<Vec<u8>, super::Error>,
+    {
+        let encryption = self.pak_encryption();
+        let mut pak_writer =
+            PakWriter::from_manifest_inner(writer, self.key, self.codecs, manifest);
+        pak_writer.alignment = self.alignment;
+        pak_writer.encryption = encryption;
+        pak_writer.write_manifest_entries(manifest, data_source)?;
+        Ok(pak_writer)
+    }
+    /// Recovers the entries of a damaged pak by scanning for inline entry headers.
+    ///
+    /// Each candidate header is checked by trial decompression or its hash before it is
+    /// accepted. Entries whose names cannot be read from the remains of the primary or directory
+    /// index are given synthetic names.
+    pub fn recover<R: Read + Seek>(self, reader: &mut R) -> Result<crate::Recovery, super::Error> {
+        let encryption = self.pak_encryption();
+        crate::recover::recover_inner(reader, self.key, self.codecs, encryption)
+    }
+    /// Reads the table of contents of an IoStore container from its `.utoc`, decrypting with
+    /// [`Builtin::IOSTORE_DEFAULT`] unless [`PakBuilder::encryption`] selects another scheme
+    pub fn iostore_reader<R: Read + Seek>(
+        self,
+        utoc: &mut R,
+    ) -> Result<crate::iostore::IoStoreReader, super::Error> {
+        let encryption = self
+            .encryption
+            .unwrap_or_else(|| Builtin::IOSTORE_DEFAULT.scheme());
+        crate::iostore::IoStoreReader::new_inner(utoc, self.key, self.codecs, encryption)
+    }
+    /// Reads a pak of any version, decrypting with [`Builtin::PAK_DEFAULT`] unless
+    /// [`PakBuilder::encryption`] selects another scheme
     pub fn reader<R: Read + Seek>(self, reader: &mut R) -> Result<PakReader, super::Error> {
-        PakReader::new_any_inner(reader, self.key)
+        let encryption = self.pak_encryption();
+        PakReader::new_any_inner(reader, self.key, self.codecs, encryption)
     }
     pub fn reader_with_version<R: Read + Seek>(
         self,
         reader: &mut R,
         version: super::Version,
     ) -> Result<PakReader, super::Error> {
-        PakReader::new_inner(reader, version, self.key)
+        let encryption = self.pak_encryption();
+        PakReader::new_inner(reader, version, self.key, self.codecs, encryption)
+    }
+    /// Writer with the version, mount point and path hash seed of `profile`, applying its other
+    /// settings as [`PakBuilder::profile`] does
+    pub fn writer_for_profile<W: Write + Seek>(
+        self,
+        writer: W,
+        profile: &GameProfile,
+    ) -> PakWriter<W> {
+        self.profile(profile).writer(
+            writer,
+            profile.version,
+            profile.mount_point.clone(),
+            profile.path_hash_seed,
+        )
     }
     pub fn writer<W: Write + Seek>(
         self,
@@ -62,14 +279,24 @@ impl PakBuilder {
         mount_point: String,
         path_hash_seed: Option<u64>,
     ) -> PakWriter<W> {
-        PakWriter::new_inner(
+        let encryption = self.pak_encryption();
+        let mut pak_writer = PakWriter::new_inner(
             writer,
             self.key,
             version,
             mount_point,
             path_hash_seed,
             self.allowed_compression,
-        )
+            self.codecs,
+        );
+        pak_writer.alignment = self.alignment;
+        pak_writer.pak.frozen_index = self.frozen_index;
+        pak_writer.pak.path_hash_index = self.path_hash_index;
+        pak_writer.pak.full_directory_index = self.full_directory_index;
+        pak_writer.pak.encrypted_index &= self.encrypt_index;
+        pak_writer.encryption = encryption;
+        pak_writer.encrypt_filter = self.encrypt_filter;
+        pak_writer
     }
 }
 
@@ -77,6 +304,8 @@ impl PakBuilder {
 pub struct PakReader {
     pak: Pak,
     key: super::Key,
+    codecs: Codecs,
+    encryption: Arc<dyn EncryptionScheme>,
 }
 
 #[derive(Debug)]
@@ -85,6 +314,14 @@ pub struct PakWriter<W: Write + Seek> {
     writer: W,
     key: super::Key,
     allowed_compression: Vec<Compression>,
+    alignment: u64,
+    codecs: Codecs,
+    pub(crate) encryption: Arc<dyn EncryptionScheme>,
+    encrypt_filter: EncryptFilter,
+    /// Stored ranges of renamed away or replaced entries of an existing pak
+    orphaned: Vec<std::ops::Range<u64>>,
+    /// Length of the existing pak, which the rewritten pak must not end short of
+    min_len: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -94,8 +331,13 @@ pub(crate) struct Pak {
     pub index_offset: Option<u64>,
     index: Index,
     encrypted_index: bool,
+    frozen_index: bool,
+    path_hash_index: bool,
+    full_directory_index: bool,
     encryption_guid: Option<u128>,
     compression: Vec<Option<Compression>>,
+    /// Compression names as spelled in the footer which was read
+    compression_names: Vec<String>,
 }
 
 impl Pak {
@@ -106,6 +348,9 @@ impl Pak {
             index_offset: None,
             index: Index::new(path_hash_seed),
             encrypted_index: false,
+            frozen_index: false,
+            path_hash_index: true,
+            full_directory_index: true,
             encryption_guid: None,
             compression: (if version.version_major() < VersionMajor::FNameBasedCompression {
                 vec![
@@ -116,6 +361,7 @@ impl Pak {
             } else {
                 vec![]
             }),
+            compression_names: vec![],
         }
     }
 }
@@ -124,6 +370,28 @@ impl Pak {
 pub(crate) struct Index {
     path_hash_seed: Option<u64>,
     entries: BTreeMap<String, super::entry::Entry>,
+    /// Directory tree keyed by directory path ("" for the root, otherwise "a/b/")
+    directories: BTreeMap<String, Directory>,
+    /// Case folded entry paths mapped to their original form
+    folded: HashMap<String, String>,
+    /// Path hashes of entries read from a path hash index without directory index, mapped to
+    /// their synthetic `hashed/` paths
+    hashed: HashMap<u64, String>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Directory {
+    directories: BTreeSet<String>,
+    files: BTreeSet<String>,
+}
+
+/// A direct child of a directory as returned by [`PakReader::list_dir`]
+#[derive(Debug, Clone, Copy)]
+pub enum DirChild<'a> {
+    /// Full path of a subdirectory, including the trailing `/`
+    Directory(&'a str),
+    /// Full path of a file
+    File(&'a str, &'a Entry),
 }
 
 impl Index {
@@ -143,21 +411,204 @@ impl Index {
     }
 
     fn add_entry(&mut self, path: String, entry: super::entry::Entry) {
+        let (directory, file_name) = split_directory(&path);
+        self.add_directory(directory)
+            .files
+            .insert(file_name.to_owned());
+        self.folded.insert(path.to_lowercase(), path.clone());
         self.entries.insert(path, entry);
     }
+
+    /// Removes the entry stored at `path` along with directories left empty by it
+    fn remove_entry(&mut self, path: &str) -> Option<super::entry::Entry> {
+        let entry = self.entries.remove(path)?;
+        let folded = path.to_lowercase();
+        if self.folded.get(&folded).is_some_and(|p| p == path) {
+            // hand the folded key to a remaining case-only duplicate, if any
+            match self.entries.keys().find(|p| p.to_lowercase() == folded) {
+                Some(duplicate) => self.folded.insert(folded, duplicate.clone()),
+                None => self.folded.remove(&folded),
+            };
+        }
+        self.hashed.retain(|_, p| p != path);
+        let (mut directory, file_name) = split_directory(path);
+        if let Some(dir) = self.directories.get_mut(directory) {
+            dir.files.remove(file_name);
+        }
+        while let Some(parent) = directory.strip_suffix('/') {
+            if self
+                .directories
+                .get(directory)
+                .is_some_and(|dir| !dir.files.is_empty() || !dir.directories.is_empty())
+            {
+                break;
+            }
+            self.directories.remove(directory);
+            let (parent, name) = split_directory(parent);
+            if let Some(dir) = self.directories.get_mut(parent) {
+                dir.directories.remove(name);
+            }
+            directory = parent;
+        }
+        Some(entry)
+    }
+
+    /// Adds an entry known only by its path hash under a synthetic path
+    fn add_hashed_entry(&mut self, hash: u64, entry: super::entry::Entry) {
+        let path = format!("hashed/{hash:016x}");
+        self.hashed.insert(hash, path.clone());
+        self.add_entry(path, entry);
+    }
+
+    /// Marks an entry with a synthetic `hashed/` path as known only by its path hash
+    fn restore_hashed_path(&mut self, path: &str) {
+        if let Some(hash) = path
+            .strip_prefix("hashed/")
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+        {
+            self.hashed.insert(hash, path.to_owned());
+        }
+    }
+
+    /// Path hash of an entry as stored in the path hash index
+    fn path_hash(&self, path: &str) -> u64 {
+        path.strip_prefix("hashed/")
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+            .filter(|hash| self.hashed.get(hash).is_some_and(|p| p == path))
+            .unwrap_or_else(|| fnv64_path(path, self.path_hash_seed.unwrap_or_default()))
+    }
+
+    /// Finds the stored path of an entry given any normalized, case folded, rooted or `/Game/`
+    /// form of its path
+    fn resolve(&self, mount_point: &str, path: &str) -> Option<&str> {
+        if let Some((path, _)) = self.entries.get_key_value(path) {
+            return Some(path);
+        }
+        let path = PakPath::new(path);
+        let mount_point = PakPath::new(mount_point);
+        let mount_root = mount_point.folded();
+        let projects = self.projects(&mount_point);
+        let candidates = path.root_candidates(&mount_point, projects.iter().map(String::as_str));
+        let mut relative = candidates.iter().filter_map(|candidate| {
+            if mount_root.is_empty() {
+                Some(candidate.as_str())
+            } else {
+                candidate
+                    .strip_prefix(&mount_root)
+                    .and_then(|relative| relative.strip_prefix('/'))
+            }
+        });
+        relative
+            .clone()
+            .find_map(|relative| self.folded.get(relative))
+            .or_else(|| {
+                // entries without a name can only be found by the hash of their path
+                let seed = self.path_hash_seed.filter(|_| !self.hashed.is_empty())?;
+                relative.find_map(|relative| self.hashed.get(&fnv64_path(relative, seed)))
+            })
+            .map(String::as_str)
+    }
+
+    /// Names of projects with a `Content` directory reachable from the mount point, used to
+    /// resolve `/Game/` paths
+    fn projects(&self, mount_point: &PakPath) -> Vec<String> {
+        let mut components = mount_point.as_str().split('/');
+        match (components.next(), components.next()) {
+            (Some(project), Some(content)) if content.eq_ignore_ascii_case("content") => {
+                vec![project.to_owned()]
+            }
+            (Some(""), None) => self
+                .directories
+                .get("")
+                .into_iter()
+                .flat_map(|root| &root.directories)
+                .filter(|project| {
+                    self.directories
+                        .get(&format!("{project}/"))
+                        .is_some_and(|dir| {
+                            dir.directories
+                                .iter()
+                                .any(|d| d.eq_ignore_ascii_case("content"))
+                        })
+                })
+                .cloned()
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Registers a directory along with all of its parents
+    fn add_directory(&mut self, directory: &str) -> &mut Directory {
+        if !self.directories.contains_key(directory) {
+            if let Some(parent) = directory.strip_suffix('/') {
+                let (parent, name) = split_directory(parent);
+                self.add_directory(parent)
+                    .directories
+                    .insert(name.to_owned());
+            }
+            self.directories
+                .insert(directory.to_owned(), Directory::default());
+        }
+        self.directories.get_mut(directory).unwrap()
+    }
+
+    fn list_dir(&self, directory: &str) -> Vec<DirChild<'_>> {
+        let directory = normalize_directory(directory);
+        let Some(dir) = self.directories.get(&directory) else {
+            return vec![];
+        };
+        let directories = dir.directories.iter().filter_map(|name| {
+            self.directories
+                .get_key_value(&format!("{directory}{name}/"))
+                .map(|(path, _)| DirChild::Directory(path.as_str()))
+        });
+        let files = dir.files.iter().filter_map(|name| {
+            self.entries
+                .get_key_value(&format!("{directory}{name}"))
+                .map(|(path, entry)| DirChild::File(path.as_str(), entry))
+        });
+        directories.chain(files).collect()
+    }
+}
+
+/// Splits a path into its directory (including trailing `/`) and file name
+fn split_directory(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(i) => path.split_at(i + 1),
+        None => ("", path),
+    }
+}
+
+/// Converts a directory query into the form used by [`Index::directories`]
+fn normalize_directory(directory: &str) -> String {
+    let directory = directory.trim_matches('/');
+    if directory.is_empty() {
+        String::new()
+    } else {
+        format!("{directory}/")
+    }
 }
 
 impl PakReader {
-    fn new_any_inner<R: Read + Seek>(
+    pub(crate) fn new_any_inner<R: Read + Seek>(
         reader: &mut R,
         key: super::Key,
+        codecs: Codecs,
+        encryption: Arc<dyn EncryptionScheme>,
     ) -> Result<Self, super::Error> {
         use std::fmt::Write;
         let mut log = "\n".to_owned();
 
         for ver in Version::iter() {
-            match Pak::read(&mut *reader, ver, &key) {
-                Ok(pak) => return Ok(Self { pak, key }),
+            match Pak::read(&mut *reader, ver, &key, &codecs, encryption.as_ref()) {
+                Ok(pak) => {
+                    return Ok(Self {
+                        pak,
+                        key,
+                        codecs,
+                        encryption,
+                    })
+                }
                 Err(err) => writeln!(log, "trying version {} failed: {}", ver, err)?,
             }
         }
@@ -168,8 +619,51 @@ impl PakReader {
         reader: &mut R,
         version: super::Version,
         key: super::Key,
+        codecs: Codecs,
+        encryption: Arc<dyn EncryptionScheme>,
     ) -> Result<Self, super::Error> {
-        Pak::read(reader, version, &key).map(|pak| Self { pak, key })
+        Pak::read(reader, version, &key, &codecs, encryption.as_ref()).map(|pak| Self {
+            pak,
+            key,
+            codecs,
+            encryption,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_recovered(
+        version: Version,
+        mount_point: String,
+        path_hash_seed: Option<u64>,
+        compression: Vec<Option<Compression>>,
+        entries: Vec<(String, Entry)>,
+        index_offset: u64,
+        key: super::Key,
+        codecs: Codecs,
+        encryption: Arc<dyn EncryptionScheme>,
+    ) -> Self {
+        let mut index = Index::new(path_hash_seed);
+        for (path, entry) in entries {
+            index.add_entry(path, entry);
+        }
+        Self {
+            pak: Pak {
+                version,
+                mount_point,
+                index_offset: Some(index_offset),
+                index,
+                encrypted_index: false,
+                frozen_index: false,
+                path_hash_index: true,
+                full_directory_index: true,
+                encryption_guid: None,
+                compression,
+                compression_names: vec![],
+            },
+            key,
+            codecs,
+            encryption,
+        }
     }
 
     pub fn version(&self) -> super::Version {
@@ -184,14 +678,70 @@ impl PakReader {
         self.pak.encrypted_index
     }
 
+    /// Frozen index flag of V9 paks, always false for other versions
+    pub fn frozen_index(&self) -> bool {
+        self.pak.frozen_index
+    }
+
+    /// Whether the index has a path hash index, always false prior to V10
+    pub fn has_path_hash_index(&self) -> bool {
+        self.pak.version.version_major() >= VersionMajor::PathHashIndex && self.pak.path_hash_index
+    }
+
+    /// Whether the index has a full directory index, always false prior to V10
+    pub fn has_full_directory_index(&self) -> bool {
+        self.pak.version.version_major() >= VersionMajor::PathHashIndex
+            && self.pak.full_directory_index
+    }
+
     pub fn encryption_guid(&self) -> Option<u128> {
         self.pak.encryption_guid
     }
 
+    pub(crate) fn key(&self) -> &super::Key {
+        &self.key
+    }
+
+    pub(crate) fn codecs(&self) -> &Codecs {
+        &self.codecs
+    }
+
+    pub(crate) fn encryption(&self) -> &Arc<dyn EncryptionScheme> {
+        &self.encryption
+    }
+
     pub fn path_hash_seed(&self) -> Option<u64> {
         self.pak.index.path_hash_seed
     }
 
+    /// Compression slots referenced by [`Entry::compression_slot`]
+    pub fn compression(&self) -> &[Option<Compression>] {
+        &self.pak.compression
+    }
+
+    /// Resolves the compression method of an entry read from this pak
+    pub fn entry_compression(&self, entry: &Entry) -> Option<Compression> {
+        entry
+            .compression_slot
+            .and_then(|slot| self.pak.compression.get(slot as usize).cloned().flatten())
+    }
+
+    /// Exports the index metadata of the pak
+    pub fn manifest(&self) -> PakManifest {
+        PakManifest {
+            version: self.pak.version,
+            mount_point: self.pak.mount_point.clone(),
+            path_hash_seed: self.pak.index.path_hash_seed,
+            encrypted_index: self.pak.encrypted_index,
+            frozen_index: self.pak.frozen_index,
+            path_hash_index: self.pak.path_hash_index,
+            full_directory_index: self.pak.full_directory_index,
+            encryption_guid: self.pak.encryption_guid,
+            compression: self.pak.compression.clone(),
+            entries: self.pak.index.entries().clone(),
+        }
+    }
+
     pub fn get<R: Read + Seek>(&self, path: &str, reader: &mut R) -> Result<Vec<u8>, super::Error> {
         let mut data = Vec::new();
         self.read_file(path, reader, &mut data)?;
@@ -204,12 +754,14 @@ impl PakReader {
         reader: &mut R,
         writer: &mut W,
     ) -> Result<(), super::Error> {
-        match self.pak.index.entries().get(path) {
-            Some(entry) => entry.read_file(
+        match self.resolve(path) {
+            Some(path) => self.pak.index.entries()[path].read_file(
                 reader,
                 self.pak.version,
                 &self.pak.compression,
+                &self.codecs,
                 &self.key,
+                self.encryption.as_ref(),
                 writer,
                 &root_path(self.mount_point(), path),
             ),
@@ -217,13 +769,69 @@ impl PakReader {
         }
     }
 
+    /// Finds the path of an entry as stored in the index. Lookups are case-insensitive and
+    /// accept any separator style, rooted (`../../../SB/Content/...`) or package (`/Game/...`)
+    /// forms of the path.
+    pub fn resolve(&self, path: &str) -> Option<&str> {
+        self.pak.index.resolve(&self.pak.mount_point, path)
+    }
+
     pub fn files(&self) -> Vec<String> {
         self.pak.index.entries().keys().cloned().collect()
     }
 
+    /// Iterates over all entries in path order without cloning
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.pak
+            .index
+            .entries()
+            .iter()
+            .map(|(path, entry)| (path.as_str(), entry))
+    }
+
+    /// Lists the direct subdirectories and files of `dir` (`""` or `"/"` for the root)
+    pub fn list_dir(&self, dir: &str) -> Vec<DirChild<'_>> {
+        self.pak.index.list_dir(dir)
+    }
+
+    /// Iterates over all entries below the directory `prefix`, recursively
+    pub fn walk(&self, prefix: &str) -> impl Iterator<Item = (&str, &Entry)> {
+        let prefix = normalize_directory(prefix);
+        self.pak
+            .index
+            .entries()
+            .range::<str, _>((
+                std::ops::Bound::Included(prefix.as_str()),
+                std::ops::Bound::Unbounded,
+            ))
+            .take_while(move |(path, _)| path.starts_with(&prefix))
+            .map(|(path, entry)| (path.as_str(), entry))
+    }
+
+    /// Iterates over all entries matching a glob pattern. `*` and `?` match within a path
+    /// component while `**` matches across directories.
+    pub fn glob<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = (&'a str, &'a Entry)> {
+        self.entries()
+            .filter(move |(path, _)| crate::glob::matches(pattern, path))
+    }
+
+    /// Maps every byte of the pak to the entry header, entry data, index structure or footer
+    /// it belongs to. Unreferenced ranges are reported as padding if zeroed.
+    pub fn layout<R: Read + Seek>(&self, reader: &mut R) -> Result<crate::Layout, super::Error> {
+        crate::layout::layout(self, reader)
+    }
+
+    /// Reads the decrypted main index, path hash index and full directory index buffers
+    pub fn index_blobs<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> Result<crate::IndexBlobs, super::Error> {
+        crate::layout::index_blobs(self, reader)
+    }
+
     pub fn get_file_entry(&self, path: &str) -> Result<Entry, Error> {
-        match self.pak.index.entries().get(path) {
-            Some(entry) => Ok((*entry).clone()),
+        match self.resolve(path) {
+            Some(path) => Ok(self.pak.index.entries()[path].clone()),
             None => Err(super::Error::MissingEntry(path.to_owned())),
         }
     }
@@ -231,34 +839,193 @@ impl PakReader {
         self,
         mut writer: W,
     ) -> Result<PakWriter<W>, super::Error> {
+        let min_len = writer.seek(io::SeekFrom::End(0))?;
         writer.seek(io::SeekFrom::Start(self.pak.index_offset.unwrap()))?;
         Ok(PakWriter {
-            allowed_compression: self.pak.compression.iter().filter_map(|c| *c).collect(),
+            allowed_compression: writable_compression(&self.pak.compression),
             pak: self.pak,
             key: self.key,
             writer,
+            alignment: 1,
+            codecs: self.codecs,
+            encryption: self.encryption.clone(),
+            encrypt_filter: Default::default(),
+            orphaned: vec![],
+            min_len,
         })
     }
 }
 
 struct PakFileEntry {}
 impl<W: Write + Seek> PakWriter<W> {
-    fn new_inner(
+    pub(crate) fn new_inner(
         writer: W,
         key: super::Key,
         version: Version,
         mount_point: String,
         path_hash_seed: Option<u64>,
         allowed_compression: Vec<Compression>,
+        codecs: Codecs,
     ) -> Self {
         PakWriter {
-            pak: Pak::new(version, mount_point, path_hash_seed),
+            pak: Pak {
+                // new paks have an encrypted index if they are written with a key
+                encrypted_index: !matches!(key, super::Key::None),
+                ..Pak::new(version, mount_point, path_hash_seed)
+            },
             writer,
             key,
             allowed_compression,
+            alignment: 1,
+            codecs,
+            encryption: Builtin::PAK_DEFAULT.scheme(),
+            encrypt_filter: Default::default(),
+            orphaned: vec![],
+            min_len: 0,
         }
     }
 
+    /// Rebuilds a pak with the metadata of `manifest`. File contents are requested from
+    /// `data_source` by path, uncompressed, and written in the order of the original offsets
+    /// using the compression slot, block size and timestamp of each entry.
+    pub fn from_manifest<F>(
+        writer: W,
+        key: super::Key,
+        manifest: &PakManifest,
+        data_source: F,
+    ) -> Result<Self, super::Error>
+    where
+        F: FnMut(&str) -> Result<Vec<u8>, super::Error>,
+    {
+        let mut pak_writer = Self::from_manifest_inner(writer, key, Codecs::default(), manifest);
+        pak_writer.write_manifest_entries(manifest, data_source)?;
+        Ok(pak_writer)
+    }
+
+    fn from_manifest_inner(
+        writer: W,
+        key: super::Key,
+        codecs: Codecs,
+        manifest: &PakManifest,
+    ) -> Self {
+        let mut pak_writer = Self::new_inner(
+            writer,
+            key,
+            manifest.version,
+            manifest.mount_point.clone(),
+            manifest.path_hash_seed,
+            writable_compression(&manifest.compression),
+            codecs,
+        );
+        pak_writer.pak.compression = manifest.compression.clone();
+        pak_writer.pak.encryption_guid = manifest.encryption_guid;
+        pak_writer.pak.encrypted_index = manifest.encrypted_index;
+        pak_writer.pak.frozen_index = manifest.frozen_index;
+        pak_writer.pak.path_hash_index = manifest.path_hash_index;
+        pak_writer.pak.full_directory_index = manifest.full_directory_index;
+        pak_writer
+    }
+
+    fn write_manifest_entries<F>(
+        &mut self,
+        manifest: &PakManifest,
+        mut data_source: F,
+    ) -> Result<(), super::Error>
+    where
+        F: FnMut(&str) -> Result<Vec<u8>, super::Error>,
+    {
+        let mut entries = manifest.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, entry)| entry.offset);
+        for (path, entry) in entries {
+            if entry.is_deleted() {
+                self.write_delete_record(path)?;
+                continue;
+            }
+            let compression = entry
+                .compression_slot
+                .and_then(|slot| manifest.compression.get(slot as usize).cloned().flatten());
+            let mut partial_entry = build_partial_entry(
+                &self.codecs,
+                Vec::from_iter(compression).as_slice(),
+                data_source(path)?,
+                match entry.is_encrypted() {
+                    true => &self.key,
+                    false => &super::Key::None,
+                },
+                self.encryption.as_ref(),
+                &root_path(&self.pak.mount_point, path),
+                entry.compression_block_size,
+            )?;
+            partial_entry.timestamp = entry.timestamp;
+            self.write_entry(path.clone(), partial_entry)?;
+        }
+        if !manifest.full_directory_index {
+            // entries of paks without directory index are named after their path hash
+            for path in manifest.entries.keys() {
+                self.pak.index.restore_hashed_path(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writer for a pak with the metadata of `pak` but none of its entries
+    pub(crate) fn with_metadata_of(writer: W, pak: &PakReader) -> Self {
+        let mut index = Index::new(pak.pak.index.path_hash_seed);
+        index.hashed = pak.pak.index.hashed.clone();
+        PakWriter {
+            pak: Pak {
+                index_offset: None,
+                index,
+                ..pak.pak.clone()
+            },
+            writer,
+            key: pak.key.clone(),
+            allowed_compression: writable_compression(&pak.pak.compression),
+            alignment: 1,
+            codecs: pak.codecs.clone(),
+            encryption: pak.encryption.clone(),
+            encrypt_filter: Default::default(),
+            orphaned: vec![],
+            min_len: 0,
+        }
+    }
+
+    /// Writes an entry with its stored, possibly compressed and encrypted `data` as is. Only the
+    /// offset and the absolute block offsets of versions prior to V5 are updated.
+    pub(crate) fn write_raw_entry(
+        &mut self,
+        path: String,
+        mut entry: Entry,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if !entry.is_deleted() {
+            let offset = self.writer.stream_position()?;
+            if self.pak.version.version_major() < VersionMajor::RelativeChunkOffsets {
+                for block in entry.blocks.iter_mut().flatten() {
+                    block.start = block.start - entry.offset + offset;
+                    block.end = block.end - entry.offset + offset;
+                }
+            }
+            entry.offset = offset;
+            entry.write(
+                &mut self.writer,
+                self.pak.version,
+                crate::entry::EntryLocation::Data,
+            )?;
+            self.writer.write_all(data)?;
+        }
+        self.pak.index.add_entry(path, entry);
+        Ok(())
+    }
+
+    pub fn mount_point(&self) -> &str {
+        &self.pak.mount_point
+    }
+
+    pub(crate) fn set_encrypted_index(&mut self, encrypted_index: bool) {
+        self.pak.encrypted_index = encrypted_index;
+    }
+
     pub fn into_writer(self) -> W {
         self.writer
     }
@@ -269,31 +1036,175 @@ impl<W: Write + Seek> PakWriter<W> {
         allow_compress: bool,
         data: impl AsRef<[u8]>,
     ) -> Result<(), super::Error> {
+        let encrypt = (self.encrypt_filter.0)(path);
+        self.write_file_with_flags(path, allow_compress, encrypt, None, data)
+    }
+
+    /// Like [`PakWriter::write_file`] but only encrypts the entry if `encrypt` is set
+    pub(crate) fn write_file_with_flags(
+        &mut self,
+        path: &str,
+        allow_compress: bool,
+        encrypt: bool,
+        timestamp: Option<u64>,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), super::Error> {
+        let mut partial_entry = build_partial_entry(
+            &self.codecs,
+            if allow_compress {
+                &self.allowed_compression
+            } else {
+                &[]
+            },
+            data.as_ref(),
+            if encrypt {
+                &self.key
+            } else {
+                &super::Key::None
+            },
+            self.encryption.as_ref(),
+            &root_path(&self.pak.mount_point, path),
+            COMPRESSION_BLOCK_SIZE,
+        )?;
+        partial_entry.timestamp = timestamp;
+        self.write_entry(path.to_string(), partial_entry)
+    }
+
+    /// Writes a delete record which hides `path` in lower priority paks
+    pub fn write_delete_record(&mut self, path: &str) -> Result<(), super::Error> {
+        if self.pak.version.version_major() < VersionMajor::DeleteRecords {
+            return Err(Error::Other(format!(
+                "cannot write delete record for {path:?} prior to DeleteRecords (pak version 6)"
+            )));
+        }
         self.pak.index.add_entry(
             path.to_string(),
-            Entry::write_file(
-                &mut self.writer,
-                self.pak.version,
-                &mut self.pak.compression,
-                if allow_compress {
-                    &self.allowed_compression
-                } else {
-                    &[]
-                },
-                data.as_ref(),
-                &self.key,
-                &root_path(&self.pak.mount_point, path),
-            )?,
+            Entry {
+                offset: 0,
+                compressed: 0,
+                uncompressed: 0,
+                compression_slot: None,
+                timestamp: None,
+                hash: Some(Hash::default()),
+                blocks: None,
+                flags: 2,
+                compression_block_size: 0,
+            },
         );
+        Ok(())
+    }
 
+    pub(crate) fn write_file_with_compression(
+        &mut self,
+        path: &str,
+        compression: Option<Compression>,
+        encrypt: bool,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), super::Error> {
+        let partial_entry = build_partial_entry(
+            &self.codecs,
+            &Vec::from_iter(compression),
+            data.as_ref(),
+            if encrypt {
+                &self.key
+            } else {
+                &super::Key::None
+            },
+            self.encryption.as_ref(),
+            &root_path(&self.pak.mount_point, path),
+            COMPRESSION_BLOCK_SIZE,
+        )?;
+        self.write_entry(path.to_string(), partial_entry)
+    }
+
+    /// Moves the entry at `old` to `new` without rewriting its data. `new` is normalized like a
+    /// [`PakPath`] and may be rooted if it lies under the mount point. Entries whose encrypted
+    /// range depends on their path under the selected [`EncryptionScheme`] must be replaced
+    /// instead.
+    pub fn rename_entry(&mut self, old: &str, new: &str) -> Result<(), super::Error> {
+        let old = self
+            .pak
+            .index
+            .resolve(&self.pak.mount_point, old)
+            .ok_or_else(|| Error::MissingEntry(old.to_owned()))?
+            .to_owned();
+        if self.pak.index.resolve(&self.pak.mount_point, new).is_some() {
+            return Err(Error::DuplicateEntry(new.to_owned()));
+        }
+        let new = relative_path(&self.pak.mount_point, new).ok_or_else(|| {
+            Error::Other(format!(
+                "{new:?} is not a file under the mount point {:?}",
+                self.pak.mount_point
+            ))
+        })?;
+        let entry = &self.pak.index.entries()[&old];
+        if entry.is_encrypted() {
+            let len = pad_length(entry.compressed as usize, 16);
+            let old_root = root_path(&self.pak.mount_point, &old);
+            let new_root = root_path(&self.pak.mount_point, &new);
+            if self.encryption.encrypted_len(&old_root, len)
+                != self.encryption.encrypted_len(&new_root, len)
+            {
+                return Err(Error::Other(format!(
+                    "renaming {old:?} to {new:?} changes its encrypted range, replace it instead"
+                )));
+            }
+        }
+        let entry = self.pak.index.remove_entry(&old).unwrap();
+        self.pak.index.add_entry(new, entry);
+        Ok(())
+    }
+
+    /// Writes `data` as the new content of the existing entry at `path`, keeping its
+    /// compression method, encryption and timestamp. The previous data stays in the pak as an
+    /// orphaned range.
+    pub fn replace_entry(&mut self, path: &str, data: impl AsRef<[u8]>) -> Result<(), Error> {
+        let path = self
+            .pak
+            .index
+            .resolve(&self.pak.mount_point, path)
+            .ok_or_else(|| Error::MissingEntry(path.to_owned()))?
+            .to_owned();
+        let entry = self.pak.index.entries()[&path].clone();
+        let compression = entry
+            .compression_slot
+            .and_then(|slot| self.pak.compression.get(slot as usize).cloned().flatten());
+        let mut partial_entry = build_partial_entry(
+            &self.codecs,
+            &Vec::from_iter(compression),
+            data.as_ref(),
+            match entry.is_encrypted() {
+                true => &self.key,
+                false => &super::Key::None,
+            },
+            self.encryption.as_ref(),
+            &root_path(&self.pak.mount_point, &path),
+            match entry.compression_block_size {
+                0 => COMPRESSION_BLOCK_SIZE,
+                size => size,
+            },
+        )?;
+        partial_entry.timestamp = entry.timestamp;
+        self.write_entry(path, partial_entry)?;
+        if !entry.is_deleted() {
+            self.orphaned.push(entry.stored_range(self.pak.version));
+        }
         Ok(())
     }
 
+    /// Stored ranges of entries which were replaced and are no longer referenced by the index
+    pub fn orphaned(&self) -> &[std::ops::Range<u64>] {
+        &self.orphaned
+    }
+
     pub fn entry_builder(&self) -> EntryBuilder {
         EntryBuilder {
             allowed_compression: self.allowed_compression.clone(),
             key: self.key.clone(),
             mount_point: self.pak.mount_point.clone(),
+            codecs: self.codecs.clone(),
+            encryption: self.encryption.clone(),
+            encrypt_filter: self.encrypt_filter.clone(),
         }
     }
 
@@ -302,7 +1213,13 @@ impl<W: Write + Seek> PakWriter<W> {
         path: String,
         partial_entry: PartialEntry<D>,
     ) -> Result<(), Error> {
-        let stream_position = self.writer.stream_position()?;
+        let mut stream_position = self.writer.stream_position()?;
+        let data_offset = stream_position + partial_entry.header_size(self.pak.version);
+        let padding = data_offset.next_multiple_of(self.alignment) - data_offset;
+        if padding > 0 {
+            self.writer.write_all(&vec![0; padding as usize])?;
+            stream_position += padding;
+        }
 
         let entry = partial_entry.build_entry(
             self.pak.version,
@@ -322,24 +1239,53 @@ impl<W: Write + Seek> PakWriter<W> {
         Ok(())
     }
     pub fn write_index(mut self) -> Result<W, super::Error> {
-        self.pak.write(&mut self.writer, &self.key)?;
+        self.pak.write(
+            &mut self.writer,
+            &self.key,
+            self.encryption.as_ref(),
+            self.min_len,
+        )?;
         Ok(self.writer)
     }
 }
 
+/// Methods of existing compression slots which new entries can be compressed with, i.e. all
+/// but [`Compression::Unknown`]
+pub(crate) fn writable_compression(slots: &[Option<Compression>]) -> Vec<Compression> {
+    slots
+        .iter()
+        .flatten()
+        .filter(|c| !matches!(c, Compression::Unknown(_)))
+        .cloned()
+        .collect()
+}
+
+/// Path of an entry relative to the root, as used to derive the encrypted range of an entry.
+///
+/// Leading `../` components and `/` of the mount point are removed so `../../../SB/Content/`,
+/// `SB/Content/` and `/SB/Content/` all map `Art/a.uasset` to `SB/Content/Art/a.uasset`, while
+/// `/Game/` maps it to `Game/Art/a.uasset`.
 fn root_path(mount_point: &str, path: &str) -> String {
-    let path = format!("{}/{}", mount_point, path);
-
-    let mut last = false;
-    let path = path
-        .chars()
-        .filter(|&c| {
-            let keep = c != '/' || !last;
-            last = c == '/';
-            keep
-        })
-        .collect::<String>();
-    path.strip_prefix("../../../").unwrap().to_string()
+    PakPath::new(&format!("{mount_point}/{path}"))
+        .as_str()
+        .to_owned()
+}
+
+/// Normalized form of `path` relative to the mount point as stored in the index. Rooted paths
+/// are made relative by removing the mount point, `None` if they lie outside of it.
+fn relative_path(mount_point: &str, path: &str) -> Option<String> {
+    let path = PakPath::new(path);
+    let mount_point = PakPath::new(mount_point);
+    let relative = match path.as_str() {
+        relative if !path.is_rooted() || mount_point.as_str().is_empty() => relative,
+        rooted => {
+            let (root, relative) = rooted.split_at_checked(mount_point.as_str().len())?;
+            relative
+                .strip_prefix('/')
+                .filter(|_| root.eq_ignore_ascii_case(mount_point.as_str()))?
+        }
+    };
+    (!relative.is_empty()).then(|| relative.to_owned())
 }
 
 struct Data<'d>(Box<dyn AsRef<[u8]> + Send + Sync + 'd>);
@@ -355,6 +1301,9 @@ pub struct EntryBuilder {
     #[allow(unused)]
     key: super::Key,
     mount_point: String,
+    codecs: Codecs,
+    encryption: Arc<dyn EncryptionScheme>,
+    encrypt_filter: EncryptFilter,
 }
 impl EntryBuilder {
     /// Builds an entry in memory (compressed if requested) which must be written out later
@@ -364,14 +1313,19 @@ impl EntryBuilder {
         data: D,
         path: &str,
     ) -> Result<PartialEntry<D>, Error> {
-        let compression = compress
-            .then_some(self.allowed_compression.as_slice())
-            .unwrap_or_default();
+        let compression = if compress {
+            self.allowed_compression.as_slice()
+        } else {
+            &[]
+        };
         build_partial_entry(
+            &self.codecs,
             compression,
             data,
-            &self.key,
+            self.encrypt_filter.key(&self.key, path),
+            self.encryption.as_ref(),
             &root_path(&self.mount_point, path),
+            COMPRESSION_BLOCK_SIZE,
         )
     }
 }
@@ -381,10 +1335,12 @@ impl Pak {
         reader: &mut R,
         version: super::Version,
         #[allow(unused)] key: &super::Key,
+        codecs: &Codecs,
+        #[allow(unused)] encryption: &dyn EncryptionScheme,
     ) -> Result<Self, super::Error> {
         // read footer to get index, encryption & compression info
         reader.seek(io::SeekFrom::End(-version.size()))?;
-        let footer = super::footer::Footer::read(reader, version)?;
+        let footer = super::footer::Footer::read(reader, version, codecs)?;
         // read index to get all the entry info
         reader.seek(io::SeekFrom::Start(footer.index_offset))?;
 
@@ -396,127 +1352,156 @@ impl Pak {
             #[cfg(not(feature = "encryption"))]
             return Err(super::Error::Encryption);
             #[cfg(feature = "encryption")]
-            crate::data::decrypt(key, &mut index)?;
+            crate::data::decrypt_index(encryption, key, &mut index)?;
         }
 
-
         let mut index = io::Cursor::new(index);
         let mount_point = index.read_string()?;
         let len = index.read_u32::<LE>()? as usize;
 
-        let index = if version.version_major() >= VersionMajor::PathHashIndex {
-            let path_hash_seed = index.read_u64::<LE>()?;
-
-            // Left in for potential desire to verify path index hashes.
-            let _path_hash_index = if index.read_u32::<LE>()? != 0 {
-                let path_hash_index_offset = index.read_u64::<LE>()?;
-                let path_hash_index_size = index.read_u64::<LE>()?;
-                let _path_hash_index_hash = index.read_len(20)?;
+        let (index, path_hash_index, full_directory_index) =
+            if version.version_major() >= VersionMajor::PathHashIndex {
+                let path_hash_seed = index.read_u64::<LE>()?;
 
-                reader.seek(io::SeekFrom::Start(path_hash_index_offset))?;
-                let mut path_hash_index_buf = reader.read_len(path_hash_index_size as usize)?;
-                // TODO verify hash
+                let path_hash_index = if index.read_u32::<LE>()? != 0 {
+                    let path_hash_index_offset = index.read_u64::<LE>()?;
+                    let path_hash_index_size = index.read_u64::<LE>()?;
+                    let _path_hash_index_hash = index.read_len(20)?;
 
-                if footer.encrypted {
-                    #[cfg(not(feature = "encryption"))]
-                    return Err(super::Error::Encryption);
-                    #[cfg(feature = "encryption")]
-                    crate::data::decrypt(key, &mut path_hash_index_buf)?;
-                }
-
-                let mut path_hash_index = vec![];
-                let mut phi_reader = io::Cursor::new(&mut path_hash_index_buf);
-                for _ in 0..phi_reader.read_u32::<LE>()? {
-                    let hash = phi_reader.read_u64::<LE>()?;
-                    let encoded_entry_offset = phi_reader.read_u32::<LE>()?;
-                    path_hash_index.push((hash, encoded_entry_offset));
-                }
+                    reader.seek(io::SeekFrom::Start(path_hash_index_offset))?;
+                    let mut path_hash_index_buf = reader.read_len(path_hash_index_size as usize)?;
+                    // TODO verify hash
 
-                Some(path_hash_index)
-            } else {
-                None
-            };
+                    if footer.encrypted {
+                        #[cfg(not(feature = "encryption"))]
+                        return Err(super::Error::Encryption);
+                        #[cfg(feature = "encryption")]
+                        crate::data::decrypt_index(encryption, key, &mut path_hash_index_buf)?;
+                    }
 
-            // Left in for potential desire to verify full directory index hashes.
-            let full_directory_index = if index.read_u32::<LE>()? != 0 {
-                let full_directory_index_offset = index.read_u64::<LE>()?;
-                let full_directory_index_size = index.read_u64::<LE>()?;
-                let _full_directory_index_hash = index.read_len(20)?;
-
-                reader.seek(io::SeekFrom::Start(full_directory_index_offset))?;
-                #[allow(unused_mut)]
-                let mut full_directory_index =
-                    reader.read_len(full_directory_index_size as usize)?;
-                // TODO verify hash
-
-                if footer.encrypted {
-                    #[cfg(not(feature = "encryption"))]
-                    return Err(super::Error::Encryption);
-                    #[cfg(feature = "encryption")]
-                    crate::data::decrypt(key, &mut full_directory_index)?;
-                }
-                let mut fdi = io::Cursor::new(full_directory_index);
-
-                let dir_count = fdi.read_u32::<LE>()? as usize;
-                let mut directories = BTreeMap::new();
-                for _ in 0..dir_count {
-                    let dir_name = fdi.read_string()?;
-                    let file_count = fdi.read_u32::<LE>()? as usize;
-                    let mut files = BTreeMap::new();
-                    for _ in 0..file_count {
-                        let file_name = fdi.read_string()?;
-                        files.insert(file_name, fdi.read_u32::<LE>()?);
+                    let mut path_hash_index = vec![];
+                    let mut phi_reader = io::Cursor::new(&mut path_hash_index_buf);
+                    for _ in 0..phi_reader.read_u32::<LE>()? {
+                        let hash = phi_reader.read_u64::<LE>()?;
+                        let encoded_entry_offset = phi_reader.read_u32::<LE>()?;
+                        path_hash_index.push((hash, encoded_entry_offset));
                     }
-                    directories.insert(dir_name, files);
-                }
-                Some(directories)
-            } else {
-                None
-            };
-            let size = index.read_u32::<LE>()? as usize;
-            let encoded_entries = index.read_len(size)?;
 
-            let mut entries_by_path = BTreeMap::new();
-            if let Some(fdi) = &full_directory_index {
+                    Some(path_hash_index)
+                } else {
+                    None
+                };
+
+                let full_directory_index = if index.read_u32::<LE>()? != 0 {
+                    let full_directory_index_offset = index.read_u64::<LE>()?;
+                    let full_directory_index_size = index.read_u64::<LE>()?;
+                    let _full_directory_index_hash = index.read_len(20)?;
+
+                    reader.seek(io::SeekFrom::Start(full_directory_index_offset))?;
+                    #[allow(unused_mut)]
+                    let mut full_directory_index =
+                        reader.read_len(full_directory_index_size as usize)?;
+                    // TODO verify hash
+
+                    if footer.encrypted {
+                        #[cfg(not(feature = "encryption"))]
+                        return Err(super::Error::Encryption);
+                        #[cfg(feature = "encryption")]
+                        crate::data::decrypt_index(encryption, key, &mut full_directory_index)?;
+                    }
+                    let mut fdi = io::Cursor::new(full_directory_index);
+
+                    let dir_count = fdi.read_u32::<LE>()? as usize;
+                    let mut directories = BTreeMap::new();
+                    for _ in 0..dir_count {
+                        let dir_name = fdi.read_string()?;
+                        let file_count = fdi.read_u32::<LE>()? as usize;
+                        let mut files = BTreeMap::new();
+                        for _ in 0..file_count {
+                            let file_name = fdi.read_string()?;
+                            files.insert(file_name, fdi.read_u32::<LE>()?);
+                        }
+                        directories.insert(dir_name, files);
+                    }
+                    Some(directories)
+                } else {
+                    None
+                };
+                let size = index.read_u32::<LE>()? as usize;
+                let encoded_entries = index.read_len(size)?;
+                // entries which cannot be encoded (e.g. delete records) are stored in full
+                let unencoded_entries =
+                    ReadExt::read_array(&mut index, |r| super::entry::Entry::read(r, version))?;
+
+                let mut entries_by_path = Index::new(Some(path_hash_seed));
                 let mut encoded_entries = io::Cursor::new(&encoded_entries);
-                for (dir_name, dir) in fdi {
-                    for (file_name, encoded_offset) in dir {
-                        if *encoded_offset == 0x80000000 {
-                            println!("{file_name:?} has invalid offset: 0x{encoded_offset:08x}");
-                            continue;
+                let mut read_entry = |name: &dyn std::fmt::Debug, encoded_offset: u32| {
+                    Ok::<_, super::Error>(match encoded_offset as i32 {
+                        i32::MIN => {
+                            println!("{name:?} has invalid offset: 0x{encoded_offset:08x}");
+                            None
+                        }
+                        // negative offsets index into the unencoded entries
+                        offset if offset < 0 => Some(
+                            unencoded_entries
+                                .get((-(offset + 1)) as usize)
+                                .cloned()
+                                .ok_or_else(|| {
+                                    super::Error::Other(format!(
+                                        "{name:?} references missing unencoded entry {}",
+                                        -(offset + 1)
+                                    ))
+                                })?,
+                        ),
+                        offset => {
+                            encoded_entries.seek(io::SeekFrom::Start(offset as u64))?;
+                            Some(super::entry::Entry::read_encoded(
+                                &mut encoded_entries,
+                                version,
+                            )?)
+                        }
+                    })
+                };
+                if let Some(fdi) = &full_directory_index {
+                    for (dir_name, dir) in fdi {
+                        for (file_name, encoded_offset) in dir {
+                            let Some(entry) = read_entry(file_name, *encoded_offset)? else {
+                                continue;
+                            };
+                            let path = format!(
+                                "{}{}",
+                                dir_name.strip_prefix('/').unwrap_or(dir_name),
+                                file_name
+                            );
+                            entries_by_path.add_entry(path, entry);
+                        }
+                        // keep directories which hold no files
+                        entries_by_path.add_directory(&normalize_directory(dir_name));
+                    }
+                } else if let Some(phi) = &path_hash_index {
+                    // without a directory index the paths are only known by their hash
+                    for (hash, encoded_offset) in phi {
+                        if let Some(entry) = read_entry(hash, *encoded_offset)? {
+                            entries_by_path.add_hashed_entry(*hash, entry);
                         }
-                        encoded_entries.seek(io::SeekFrom::Start(*encoded_offset as u64))?;
-                        let entry =
-                            super::entry::Entry::read_encoded(&mut encoded_entries, version)?;
-                        let path = format!(
-                            "{}{}",
-                            dir_name.strip_prefix('/').unwrap_or(dir_name),
-                            file_name
-                        );
-                        entries_by_path.insert(path, entry);
                     }
                 }
-            }
 
-            assert_eq!(index.read_u32::<LE>()?, 0, "remaining index bytes are 0"); // TODO possibly remaining unencoded entries?
-
-            Index {
-                path_hash_seed: Some(path_hash_seed),
-                entries: entries_by_path,
-            }
-        } else {
-            let mut entries = BTreeMap::new();
-            for _ in 0..len {
-                entries.insert(
-                    index.read_string()?,
-                    super::entry::Entry::read(&mut index, version)?,
-                );
-            }
-            Index {
-                path_hash_seed: None,
-                entries,
-            }
-        };
+                (
+                    entries_by_path,
+                    path_hash_index.is_some(),
+                    full_directory_index.is_some(),
+                )
+            } else {
+                let mut entries = Index::new(None);
+                for _ in 0..len {
+                    entries.add_entry(
+                        index.read_string()?,
+                        super::entry::Entry::read(&mut index, version)?,
+                    );
+                }
+                (entries, true, true)
+            };
 
         Ok(Pak {
             version,
@@ -524,8 +1509,12 @@ impl Pak {
             index_offset: Some(footer.index_offset),
             index,
             encrypted_index: footer.encrypted,
+            frozen_index: footer.frozen,
+            path_hash_index,
+            full_directory_index,
             encryption_guid: footer.encryption_uuid,
             compression: footer.compression,
+            compression_names: footer.compression_names,
         })
     }
 
@@ -533,7 +1522,21 @@ impl Pak {
         &self,
         writer: &mut W,
         #[allow(unused)] key: &super::Key,
+        #[allow(unused)] encryption: &dyn EncryptionScheme,
+        min_len: u64,
     ) -> Result<(), super::Error> {
+        // the index is only encrypted if the pak was read with or created with an encrypted index
+        #[cfg(feature = "encryption")]
+        let index_key = match (self.encrypted_index, key) {
+            (false, _) => None,
+            (true, crate::Key::Some(key)) => Some(key),
+            (true, crate::Key::None) => return Err(super::Error::Encrypted),
+        };
+        #[cfg(not(feature = "encryption"))]
+        if self.encrypted_index {
+            return Err(super::Error::Encryption);
+        }
+
         let index_offset = writer.stream_position()?;
 
         let mut index_buf = vec![];
@@ -541,7 +1544,11 @@ impl Pak {
         index_writer.write_string(&self.mount_point)?;
 
         let secondary_index = if self.version < super::Version::V10 {
-            let record_count = self.index.entries.len() as u32;
+            let record_count: u32 = narrow(
+                "entry count",
+                self.index.entries.len() as u64,
+                u32::MAX as u64,
+            )?;
             index_writer.write_u32::<LE>(record_count)?;
             for (path, entry) in &self.index.entries {
                 index_writer.write_string(path)?;
@@ -553,19 +1560,59 @@ impl Pak {
             }
             None
         } else {
-            let record_count = self.index.entries.len() as u32;
+            if !self.path_hash_index && !self.full_directory_index {
+                return Err(Error::Other(
+                    "index needs a path hash index or a full directory index".to_owned(),
+                ));
+            }
+            let record_count: u32 = narrow(
+                "entry count",
+                self.index.entries.len() as u64,
+                u32::MAX as u64,
+            )?;
             let path_hash_seed = self.index.path_hash_seed.unwrap_or_default();
             index_writer.write_u32::<LE>(record_count)?;
             index_writer.write_u64::<LE>(path_hash_seed)?;
 
-            let (encoded_entries, offsets) = {
+            let (encoded_entries, unencoded_entries, offsets) = {
                 let mut offsets = Vec::with_capacity(self.index.entries.len());
                 let mut encoded_entries = io::Cursor::new(vec![]);
+                let mut unencoded_entries = io::Cursor::new(vec![]);
+                let mut unencoded_count = 0u32;
+                unencoded_entries.write_u32::<LE>(0)?;
                 for entry in self.index.entries.values() {
-                    offsets.push(encoded_entries.get_ref().len() as u32);
-                    entry.write_encoded(&mut encoded_entries)?;
+                    if entry.is_deleted() || !entry.is_encodable() {
+                        // delete records and entries exceeding the encoded bit fields are
+                        // referenced by negative offset into the list of full entries instead
+                        unencoded_count += 1;
+                        let offset: i32 = narrow(
+                            "unencoded entry count",
+                            unencoded_count as u64,
+                            i32::MAX as u64,
+                        )?;
+                        offsets.push(-offset as u32);
+                        entry.write(
+                            &mut unencoded_entries,
+                            self.version,
+                            super::entry::EntryLocation::Index,
+                        )?;
+                    } else {
+                        // offsets are signed so the encoded records are limited to 2 GiB
+                        offsets.push(narrow(
+                            "encoded entry offset",
+                            encoded_entries.get_ref().len() as u64,
+                            i32::MAX as u64,
+                        )?);
+                        entry.write_encoded(&mut encoded_entries)?;
+                    }
                 }
-                (encoded_entries.into_inner(), offsets)
+                unencoded_entries.set_position(0);
+                unencoded_entries.write_u32::<LE>(unencoded_count)?;
+                (
+                    encoded_entries.into_inner(),
+                    unencoded_entries.into_inner(),
+                    offsets,
+                )
             };
 
             // The index is organized sequentially as:
@@ -582,7 +1629,7 @@ impl Pak {
             //         - Full Directory Index Size (u64)
             //         - Full Directory Index Hash ([u8; 20])
             //     - Encoded Index Records Size
-            //     - (Unused) File Count
+            //     - Unencoded Entry Count, followed by the unencoded entries
             // - Path Hash Index
             // - Full Directory Index
             // - Encoded Index Records; each encoded index record is (0xC bytes) from:
@@ -595,15 +1642,19 @@ impl Pak {
                 size += self.mount_point.len() as u64 + 1; // mount point string w/ NUL byte
                 size += 8; // path hash seed
                 size += 4; // record count
-                size += 4; // has path hash index (since we're generating, always true)
-                size += 8 + 8 + 20; // path hash index offset, size and hash
-                size += 4; // has full directory index (since we're generating, always true)
-                size += 8 + 8 + 20; // full directory index offset, size and hash
+                size += 4; // has path hash index
+                if self.path_hash_index {
+                    size += 8 + 8 + 20; // path hash index offset, size and hash
+                }
+                size += 4; // has full directory index
+                if self.full_directory_index {
+                    size += 8 + 8 + 20; // full directory index offset, size and hash
+                }
                 size += 4; // encoded entry size
                 size += encoded_entries.len() as u64;
-                size += 4; // unused file count
+                size += unencoded_entries.len() as u64; // unencoded entry count and entries
                 #[cfg(feature = "encryption")]
-                if let crate::Key::Some(_) = key {
+                if index_key.is_some() {
                     size = pad_length(size as usize, 16) as u64
                 }
                 size
@@ -612,60 +1663,67 @@ impl Pak {
             let path_hash_index_offset = index_offset + bytes_before_phi;
 
             let mut phi_buf = vec![];
-            let mut phi_writer = io::Cursor::new(&mut phi_buf);
-            generate_path_hash_index(
-                &mut phi_writer,
-                path_hash_seed,
-                &self.index.entries,
-                &offsets,
-            )?;
+            if self.path_hash_index {
+                let mut phi_writer = io::Cursor::new(&mut phi_buf);
+                generate_path_hash_index(&mut phi_writer, &self.index, &offsets)?;
+            }
 
             #[cfg(feature = "encryption")]
-            if let crate::Key::Some(_) = key {
-                crate::data::pad_zeros_to_alignment(&mut phi_buf, 16);
+            if index_key.is_some() {
+                crate::data::pad_data_to_alignment(&mut phi_buf, 16);
             }
             let phi_hash = hash(&phi_buf);
             #[cfg(feature = "encryption")]
-            if let crate::Key::Some(key) = key {
-                crate::data::encrypt(key, &mut phi_buf);
+            if let Some(key) = index_key {
+                encryption.encrypt_index(key, &mut phi_buf);
             }
 
             let full_directory_index_offset = path_hash_index_offset + phi_buf.len() as u64;
 
             let mut fdi_buf = vec![];
-            let mut fdi_writer = io::Cursor::new(&mut fdi_buf);
-            generate_full_directory_index(&mut fdi_writer, &self.index.entries, &offsets)?;
+            if self.full_directory_index {
+                let mut fdi_writer = io::Cursor::new(&mut fdi_buf);
+                generate_full_directory_index(&mut fdi_writer, &self.index.entries, &offsets)?;
+            }
 
             #[cfg(feature = "encryption")]
-            if let crate::Key::Some(_) = key {
-                crate::data::pad_zeros_to_alignment(&mut fdi_buf, 16);
+            if index_key.is_some() {
+                crate::data::pad_data_to_alignment(&mut fdi_buf, 16);
             }
             let fdi_hash = hash(&fdi_buf);
             #[cfg(feature = "encryption")]
-            if let crate::Key::Some(key) = key {
-                crate::data::encrypt(key, &mut fdi_buf);
+            if let Some(key) = index_key {
+                encryption.encrypt_index(key, &mut fdi_buf);
             }
 
-            index_writer.write_u32::<LE>(1)?; // we have path hash index
-            index_writer.write_u64::<LE>(path_hash_index_offset)?;
-            index_writer.write_u64::<LE>(phi_buf.len() as u64)?; // path hash index size
-            index_writer.write_all(&phi_hash.0)?;
+            index_writer.write_u32::<LE>(self.path_hash_index as u32)?;
+            if self.path_hash_index {
+                index_writer.write_u64::<LE>(path_hash_index_offset)?;
+                index_writer.write_u64::<LE>(phi_buf.len() as u64)?; // path hash index size
+                index_writer.write_all(&phi_hash.0)?;
+            }
 
-            index_writer.write_u32::<LE>(1)?; // we have full directory index
-            index_writer.write_u64::<LE>(full_directory_index_offset)?;
-            index_writer.write_u64::<LE>(fdi_buf.len() as u64)?; // path hash index size
-            index_writer.write_all(&fdi_hash.0)?;
+            index_writer.write_u32::<LE>(self.full_directory_index as u32)?;
+            if self.full_directory_index {
+                index_writer.write_u64::<LE>(full_directory_index_offset)?;
+                index_writer.write_u64::<LE>(fdi_buf.len() as u64)?; // full directory index size
+                index_writer.write_all(&fdi_hash.0)?;
+            }
 
-            index_writer.write_u32::<LE>(encoded_entries.len() as u32)?;
+            index_writer.write_u32::<LE>(narrow(
+                "encoded entries size",
+                encoded_entries.len() as u64,
+                u32::MAX as u64,
+            )?)?;
             index_writer.write_all(&encoded_entries)?;
 
-            index_writer.write_u32::<LE>(0)?;
+            index_writer.write_all(&unencoded_entries)?;
 
             Some((phi_buf, fdi_buf))
         };
 
         let mut footer = super::footer::Footer {
-            encryption_uuid: None,
+            encryption_uuid: self.encryption_guid,
             encrypted: false,
             magic: super::MAGIC,
             version: self.version,
@@ -673,17 +1731,17 @@ impl Pak {
             index_offset,
             index_size: 0,
             hash: Default::default(),
-            frozen: false,
+            frozen: self.frozen_index,
             compression: self.compression.clone(),
+            compression_names: self.compression_names.clone(),
         };
 
         #[cfg(feature = "encryption")]
-        if let crate::Key::Some(key) = key {
-            crate::data::pad_zeros_to_alignment(&mut index_buf, 16);
+        if let Some(key) = index_key {
+            crate::data::pad_data_to_alignment(&mut index_buf, 16);
             footer.hash = hash(&index_buf);
-            crate::data::encrypt(key, &mut index_buf);
+            encryption.encrypt_index(key, &mut index_buf);
             footer.encrypted = true;
-            footer.encryption_uuid = Some(Default::default());
         } else {
             footer.hash = hash(&index_buf);
         }
@@ -700,13 +1758,20 @@ impl Pak {
             writer.write_all(&fdi_buf[..])?;
         }
 
+        // the footer has to end the file, so a pak rewritten in place with a smaller index is
+        // padded up to the footer of the original
+        let footer_offset = writer.stream_position()?;
+        let footer_len = self.version.size() as u64;
+        if footer_offset + footer_len < min_len {
+            writer.write_all(&vec![0; (min_len - footer_len - footer_offset) as usize])?;
+        }
         footer.write(writer)?;
 
         Ok(())
     }
 }
 
-fn hash(data: &[u8]) -> Hash {
+pub(crate) fn hash(data: &[u8]) -> Hash {
     use sha1::{Digest, Sha1};
     let mut hasher = Sha1::new();
     hasher.update(data);
@@ -715,13 +1780,12 @@ fn hash(data: &[u8]) -> Hash {
 
 fn generate_path_hash_index<W: Write>(
     writer: &mut W,
-    path_hash_seed: u64,
-    entries: &BTreeMap<String, super::entry::Entry>,
+    index: &Index,
     offsets: &Vec<u32>,
 ) -> Result<(), super::Error> {
-    writer.write_u32::<LE>(entries.len() as u32)?;
-    for (path, offset) in entries.keys().zip(offsets) {
-        let path_hash = fnv64_path(path, path_hash_seed);
+    writer.write_u32::<LE>(index.entries.len() as u32)?;
+    for (path, offset) in index.entries.keys().zip(offsets) {
+        let path_hash = index.path_hash(path);
         writer.write_u64::<LE>(path_hash)?;
         writer.write_u32::<LE>(*offset)?;
     }
@@ -799,6 +1863,37 @@ fn generate_full_directory_index<W: Write>(
 mod test {
     use super::*;
 
+    #[test]
+    fn test_root_path() {
+        for mount_point in [
+            "../../../",
+            "../../../SB/Content/",
+            "../../../SB/Content",
+            "SB/Content/",
+            "/SB/Content/",
+            "/",
+            "",
+            "/Game/",
+            "../mount/point/root/",
+        ] {
+            let expected = match mount_point {
+                "../../../" | "/" | "" => "Art/a.uasset",
+                "/Game/" => "Game/Art/a.uasset",
+                "../mount/point/root/" => "mount/point/root/Art/a.uasset",
+                _ => "SB/Content/Art/a.uasset",
+            };
+            assert_eq!(
+                root_path(mount_point, "Art/a.uasset"),
+                expected,
+                "{mount_point}"
+            );
+        }
+        assert_eq!(
+            root_path("../../../SB/Content/", "/Art//a.uasset"),
+            "SB/Content/Art/a.uasset"
+        );
+    }
+
     #[test]
     fn test_split_path_child() {
         assert_eq!(
@@ -815,4 +1910,43 @@ mod test {
         assert_eq!(split_path_child("/"), None);
         assert_eq!(split_path_child(""), None);
     }
+
+    #[test]
+    fn test_unencodable_entry() {
+        // more blocks than fit the encoded bit field
+        let blocks: Vec<_> = (0..0x10000u64)
+            .map(|i| crate::entry::Block {
+                start: i * 0x100,
+                end: (i + 1) * 0x100,
+            })
+            .collect();
+        let mut pak = Pak::new(Version::V11, "../../../".to_owned(), Some(0));
+        pak.compression = vec![Some(Compression::Zlib)];
+        for (path, offset) in [("a/big.bin", 0x1_0000_0000), ("a/small.bin", 0)] {
+            pak.index.add_entry(
+                path.to_owned(),
+                Entry {
+                    offset,
+                    compressed: 0x100_0000,
+                    uncompressed: 0x1_0000_0000,
+                    compression_slot: Some(0),
+                    timestamp: None,
+                    hash: Some(Hash::default()),
+                    blocks: Some(blocks[..if offset == 0 { 1 } else { 0x10000 }].to_vec()),
+                    flags: 0,
+                    compression_block_size: 0x10000,
+                },
+            );
+        }
+        let mut buf = io::Cursor::new(vec![]);
+        pak.write(&mut buf, &crate::Key::None, &crate::UnrealStandard, 0)
+            .unwrap();
+
+        let reader = crate::PakBuilder::new().reader(&mut buf).unwrap();
+        let big = reader.get_file_entry("a/big.bin").unwrap();
+        assert_eq!(big.offset, 0x1_0000_0000);
+        assert_eq!(big.uncompressed, 0x1_0000_0000);
+        assert_eq!(big.blocks.unwrap().len(), 0x10000);
+        assert_eq!(reader.get_file_entry("a/small.bin").unwrap().offset, 0);
+    }
 }
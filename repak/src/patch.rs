@@ -0,0 +1,65 @@
+use std::io::{Read, Seek, Write};
+
+use crate::{diff, Error, PakReader, PakWriter, VersionMajor};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Writes a patch pak containing only the entries of `new` which were added or whose content
+/// changed relative to `base`. Entries removed in `new` are written as delete records if the
+/// pak version supports them.
+///
+/// The patch uses the version, mount point, compression, key and encryption flags of `new`. Name
+/// the output with a `_P` suffix so it is mounted with a higher priority than the base pak.
+pub fn make_patch<RA, RB, W>(
+    base: &PakReader,
+    base_reader: &mut RA,
+    new: &PakReader,
+    new_reader: &mut RB,
+    writer: W,
+) -> Result<W>
+where
+    RA: Read + Seek,
+    RB: Read + Seek,
+    W: Write + Seek,
+{
+    let diff = diff(base, base_reader, new, new_reader)?;
+
+    let mut pak_writer = PakWriter::new_inner(
+        writer,
+        new.key().clone(),
+        new.version(),
+        new.mount_point().to_owned(),
+        new.path_hash_seed(),
+        crate::pak::writable_compression(new.compression()),
+        new.codecs().clone(),
+    );
+    pak_writer.encryption = new.encryption().clone();
+    pak_writer.set_encrypted_index(new.encrypted_index());
+
+    let changed = diff
+        .modified
+        .iter()
+        .filter(|entry| entry.content)
+        .map(|entry| &entry.path);
+    for path in diff.added.iter().chain(changed) {
+        let entry = new.get_file_entry(path)?;
+        if entry.is_deleted() {
+            continue;
+        }
+        let data = new.get(path, new_reader)?;
+        pak_writer.write_file_with_compression(
+            path,
+            new.entry_compression(&entry),
+            entry.is_encrypted(),
+            data,
+        )?;
+    }
+
+    if new.version().version_major() >= VersionMajor::DeleteRecords {
+        for path in &diff.removed {
+            pak_writer.write_delete_record(path)?;
+        }
+    }
+
+    pak_writer.write_index()
+}
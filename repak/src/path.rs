@@ -0,0 +1,129 @@
+/// A virtual file path normalized for lookups in a pak.
+///
+/// Backslashes are converted to `/`, empty and `.` components are removed and `..` components
+/// are resolved. Paths starting with `/` or `../` are rooted (e.g. `/Game/Maps/Map.umap` or
+/// `../../../SB/Content/Maps/Map.umap`) while all others are relative to the mount point of the
+/// pak. Comparisons should use [`PakPath::folded`] as Unreal treats paths case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PakPath {
+    path: String,
+    rooted: bool,
+}
+
+impl PakPath {
+    pub fn new(path: &str) -> Self {
+        let path = path.replace('\\', "/");
+        let rooted = path.starts_with('/') || path.starts_with("../");
+        let mut components = vec![];
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                component => components.push(component),
+            }
+        }
+        Self {
+            path: components.join("/"),
+            rooted,
+        }
+    }
+
+    /// Normalized path without any leading `/` or `../`
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the path is rooted rather than relative to the mount point
+    pub fn is_rooted(&self) -> bool {
+        self.rooted
+    }
+
+    /// Case folded form of the path used for case-insensitive comparison
+    pub fn folded(&self) -> String {
+        self.path.to_lowercase()
+    }
+
+    /// Folded candidates for the path relative to the root (the mount point with any leading
+    /// `../` removed), most specific first. `/Game/` and `/Engine/` package paths are expanded
+    /// to `<project>/Content/` for each candidate project name.
+    pub(crate) fn root_candidates<'p>(
+        &self,
+        mount_point: &PakPath,
+        projects: impl IntoIterator<Item = &'p str>,
+    ) -> Vec<String> {
+        let folded = self.folded();
+        let mut candidates = vec![];
+        if !self.rooted {
+            candidates.push(join(&mount_point.folded(), &folded));
+        }
+        if let Some(rest) = folded.strip_prefix("game/") {
+            for project in projects {
+                candidates.push(format!("{}/content/{rest}", project.to_lowercase()));
+            }
+        } else if let Some(rest) = folded.strip_prefix("engine/") {
+            candidates.push(format!("engine/content/{rest}"));
+        }
+        candidates.push(folded);
+        candidates
+    }
+}
+
+fn join(directory: &str, path: &str) -> String {
+    if directory.is_empty() {
+        path.to_owned()
+    } else {
+        format!("{directory}/{path}")
+    }
+}
+
+impl From<&str> for PakPath {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+impl std::fmt::Display for PakPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.rooted {
+            write!(f, "/")?;
+        }
+        write!(f, "{}", self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PakPath;
+
+    #[test]
+    fn test_normalize() {
+        let path = PakPath::new("SB\\Content//Art/./a.uasset");
+        assert_eq!(path.as_str(), "SB/Content/Art/a.uasset");
+        assert!(!path.is_rooted());
+        assert_eq!(path.folded(), "sb/content/art/a.uasset");
+
+        let path = PakPath::new("../../../SB/Content/Art/../a.uasset");
+        assert_eq!(path.as_str(), "SB/Content/a.uasset");
+        assert!(path.is_rooted());
+
+        let path = PakPath::new("/Game/Art/a.uasset");
+        assert_eq!(path.as_str(), "Game/Art/a.uasset");
+        assert!(path.is_rooted());
+        assert_eq!(path.to_string(), "/Game/Art/a.uasset");
+    }
+
+    #[test]
+    fn test_root_candidates() {
+        let mount_point = PakPath::new("../../../SB/Content/");
+        assert_eq!(
+            PakPath::new("Art/a.uasset").root_candidates(&mount_point, ["SB"]),
+            vec!["sb/content/art/a.uasset", "art/a.uasset"]
+        );
+        assert_eq!(
+            PakPath::new("/Game/Art/a.uasset").root_candidates(&mount_point, ["SB"]),
+            vec!["sb/content/art/a.uasset", "game/art/a.uasset"]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Settings which differ between games, so paks can be built for stock Unreal Engine titles and
+//! for Stellar Blade alike.
+//!
+//! Profiles are either constructed in code or, with the `serde` feature, loaded from TOML:
+//!
+//! ```toml
+//! name = "Stellar Blade"
+//! version = "V11"
+//! mount_point = "../../../"
+//! path_hash_seed = 0
+//! compression = ["Oodle"]
+//! encryption = "StellarBladePartial"
+//! engine_version = "UE4_26"
+//! ```
+
+use std::str::FromStr;
+
+use crate::{encryption::Builtin, Compression, Error, Version};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct GameProfile {
+    #[cfg_attr(feature = "serde", serde(default = "default_name"))]
+    pub name: String,
+    pub version: Version,
+    #[cfg_attr(feature = "serde", serde(default = "default_mount_point"))]
+    pub mount_point: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub path_hash_seed: Option<u64>,
+    /// Compression methods passed to [`crate::PakBuilder::compression`]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "default_compression", with = "compression_names")
+    )]
+    pub compression: Vec<Compression>,
+    /// Encryption scheme used with the AES key of the pak
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub encryption: Builtin,
+    /// AES key of the game in hex or base64, see [`GameProfile::key`]. Never set by the built-in
+    /// profiles, keys are supplied by the user or found with [`crate::keyfinder`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            deserialize_with = "deserialize_aes_key",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub aes_key: Option<String>,
+    /// Engine version for IoStore conversion, e.g. `UE4_26`
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub engine_version: Option<String>,
+}
+
+impl GameProfile {
+    pub fn stellar_blade() -> Self {
+        Self {
+            name: "Stellar Blade".to_owned(),
+            version: Version::V11,
+            mount_point: "../../../".to_owned(),
+            path_hash_seed: Some(0),
+            compression: vec![Compression::Oodle],
+            encryption: Builtin::StellarBladePartial,
+            aes_key: None,
+            engine_version: Some("UE4_26".to_owned()),
+        }
+    }
+
+    /// Settings matching UnrealPak for an unmodified engine
+    pub fn unreal(version: Version) -> Self {
+        Self {
+            name: "Unreal Engine".to_owned(),
+            version,
+            mount_point: "../../../".to_owned(),
+            path_hash_seed: None,
+            compression: vec![Compression::Zlib],
+            encryption: Builtin::UnrealStandard,
+            aes_key: None,
+            engine_version: None,
+        }
+    }
+
+    /// Parses [`GameProfile::aes_key`]. The key is not applied by [`crate::PakBuilder::profile`]
+    /// since writers would then encrypt, so readers of encrypted containers pass it to
+    /// [`crate::PakBuilder::key`].
+    #[cfg(feature = "encryption")]
+    pub fn key(&self) -> Result<Option<aes::Aes256>> {
+        self.aes_key
+            .as_deref()
+            .map(|key| crate::utils::AesKey::from_str(key).map(|key| key.0))
+            .transpose()
+    }
+
+    /// Parses a profile from TOML. `version` is required while other keys default to those of
+    /// [`GameProfile::unreal`].
+    #[cfg(feature = "serde")]
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| Error::Profile {
+            // 1-based line of the offending value, 0 when the error has no location
+            line: e
+                .span()
+                .map_or(0, |span| text[..span.start].matches('\n').count() + 1),
+            message: e.message().to_owned(),
+        })
+    }
+
+    /// Serializes the profile in the form read by [`GameProfile::from_toml`]
+    #[cfg(feature = "serde")]
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_name() -> String {
+    "Unreal Engine".to_owned()
+}
+
+#[cfg(feature = "serde")]
+fn default_mount_point() -> String {
+    "../../../".to_owned()
+}
+
+#[cfg(feature = "serde")]
+fn default_compression() -> Vec<Compression> {
+    vec![Compression::Zlib]
+}
+
+/// Compression methods by name, names which aren't built in are kept as
+/// [`Compression::Custom`] and resolved against the registered codecs when writing
+#[cfg(feature = "serde")]
+mod compression_names {
+    use super::*;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &[Compression],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|c| c.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Compression>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|name| match Compression::from_str(&name) {
+                Ok(compression) => Ok(compression),
+                Err(_) if name.is_empty() => Err(D::Error::custom("empty compression name")),
+                Err(_) => Ok(Compression::Custom(name)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_aes_key<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let key = <String as serde::Deserialize>::deserialize(deserializer)?;
+    #[cfg(feature = "encryption")]
+    if crate::utils::AesKey::from_str(&key).is_err() {
+        return Err(serde::de::Error::custom("invalid AES key"));
+    }
+    Ok(Some(key))
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toml() {
+        for profile in [
+            GameProfile::stellar_blade(),
+            GameProfile::unreal(Version::V8B),
+        ] {
+            assert_eq!(
+                GameProfile::from_toml(&profile.to_toml().unwrap()).unwrap(),
+                profile
+            );
+        }
+        assert_eq!(
+            GameProfile::from_toml(include_str!("../profiles/stellar_blade.toml")).unwrap(),
+            GameProfile::stellar_blade()
+        );
+
+        let profile = GameProfile::from_toml(concat!(
+            "# comment\n",
+            "version = \"V9\" # trailing comment\n",
+            "path_hash_seed = 0x205C_5A7D\n",
+            "compression = [\"Zstd\", \"Xor\"]\n",
+        ))
+        .unwrap();
+        assert_eq!(profile.version, Version::V9);
+        assert_eq!(profile.path_hash_seed, Some(0x205C5A7D));
+        assert_eq!(
+            profile.compression,
+            vec![Compression::Zstd, Compression::Custom("Xor".to_owned())]
+        );
+        assert_eq!(profile.encryption, Builtin::UnrealStandard);
+
+        assert!(matches!(
+            GameProfile::from_toml("name = \"x\""),
+            Err(Error::Profile { message, .. }) if message.contains("version")
+        ));
+        assert!(matches!(
+            GameProfile::from_toml("version = \"V11\"\nfoo = 1"),
+            Err(Error::Profile { line: 2, .. })
+        ));
+        assert!(matches!(
+            GameProfile::from_toml("version = \"V11\"\nencryption = 1"),
+            Err(Error::Profile { line: 2, .. })
+        ));
+        assert!(matches!(
+            GameProfile::from_toml("version = \"V11\"\nencryption = \"Rot13\""),
+            Err(Error::Profile { line: 2, .. })
+        ));
+        #[cfg(feature = "encryption")]
+        {
+            assert!(matches!(
+                GameProfile::from_toml("version = \"V11\"\naes_key = \"0C26\""),
+                Err(Error::Profile { line: 2, .. })
+            ));
+            assert!(GameProfile::stellar_blade().key().unwrap().is_none());
+            let mut profile = GameProfile::unreal(Version::V11);
+            profile.aes_key = Some("lNJbw660IOC+kU7cnVQ1oeqrXyhk4J6UAZrCBbcnp94=".to_owned());
+            assert!(profile.key().unwrap().is_some());
+            assert_eq!(
+                GameProfile::from_toml(&profile.to_toml().unwrap()).unwrap(),
+                profile
+            );
+        }
+    }
+}
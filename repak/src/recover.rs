@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+use byteorder::{ReadBytesExt, LE};
+
+use crate::{
+    encryption::Builtin, entry::Entry, ext::ReadExt, footer::Footer, Codecs, Compression,
+    EncryptionScheme, Error, Key, PakReader, Version, VersionMajor,
+};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Versions representing each distinct inline entry header layout
+const LAYOUTS: [Version; 5] = [
+    Version::V11,
+    Version::V8A,
+    Version::V4,
+    Version::V2,
+    Version::V1,
+];
+
+/// Largest compression block size considered plausible
+const MAX_COMPRESSION_BLOCK_SIZE: u32 = 0x100_0000;
+
+/// Entries with an all zero header prefix this long are not considered, which allows skipping
+/// over runs of zeros. Covers offset, sizes, compression, timestamp and hash of every layout.
+const ZERO_PREFIX: u64 = 64;
+
+const CHUNK_SIZE: usize = 0x10_0000;
+
+/// Result of [`recover`]
+#[derive(Debug)]
+pub struct Recovery {
+    /// Reader over the recovered entries. Its index offset points just past the last recovered
+    /// entry so a copy of the damaged file can be repaired with [`PakReader::into_pakwriter`].
+    pub pak: PakReader,
+    /// Entries which were structurally valid but whose data could not be verified, e.g. because
+    /// they are encrypted and no key or name is available
+    pub unverified: Vec<String>,
+}
+
+/// Recovers the entries of a pak with a damaged or missing index by scanning for the inline
+/// entry headers preceding each data record, see [`crate::PakBuilder::recover`]
+pub fn recover<R: Read + Seek>(reader: &mut R) -> Result<Recovery> {
+    recover_inner(
+        reader,
+        Key::None,
+        Codecs::default(),
+        Builtin::PAK_DEFAULT.scheme(),
+    )
+}
+
+struct Found {
+    entry: Entry,
+    verified: bool,
+}
+
+pub(crate) fn recover_inner<R: Read + Seek>(
+    reader: &mut R,
+    key: Key,
+    codecs: Codecs,
+    encryption: Arc<dyn EncryptionScheme>,
+) -> Result<Recovery> {
+    let len = reader.seek(SeekFrom::End(0))?;
+
+    // version and compression can only be trusted if the index is intact
+    let intact =
+        PakReader::new_any_inner(reader, key.clone(), codecs.clone(), encryption.clone()).ok();
+    let salvaged = match &intact {
+        Some(pak) => Salvaged {
+            mount_point: Some(pak.mount_point().to_owned()),
+            path_hash_seed: pak.path_hash_seed(),
+            names: pak
+                .entries()
+                .map(|(path, entry)| (entry.offset, path.to_owned()))
+                .collect(),
+        },
+        None => Version::iter()
+            .filter_map(|version| {
+                salvage(
+                    &mut *reader,
+                    len,
+                    version,
+                    &key,
+                    &codecs,
+                    encryption.as_ref(),
+                )
+                .ok()
+            })
+            .max_by_key(|salvaged| salvaged.names.len())
+            .unwrap_or_default(),
+    };
+    let names = &salvaged.names;
+
+    let mut best: Option<(Version, Vec<Option<Compression>>, Vec<Found>)> = None;
+    for version in match &intact {
+        Some(pak) => vec![pak.version()],
+        None => LAYOUTS.to_vec(),
+    } {
+        let (known, mut compression) = match &intact {
+            Some(pak) => (true, pak.compression().to_vec()),
+            None => (false, vec![None; 5]),
+        };
+        let mut scanner = Scanner {
+            reader: &mut *reader,
+            len,
+            version,
+            key: &key,
+            encryption: encryption.as_ref(),
+            codecs: &codecs,
+            names,
+            known,
+            compression: &mut compression,
+        };
+        let found = scanner.scan()?;
+        let score = |found: &[Found]| found.iter().filter(|f| f.verified).count();
+        if best
+            .as_ref()
+            .is_none_or(|(_, _, best)| score(&found) > score(best))
+        {
+            best = Some((version, compression, found));
+        }
+    }
+    let (version, compression, found) = best.unwrap();
+
+    let index_offset = found
+        .iter()
+        .map(|f| data_end(&f.entry, version))
+        .max()
+        .unwrap_or_default();
+    let mut unverified = vec![];
+    let entries = found
+        .into_iter()
+        .map(|Found { entry, verified }| {
+            let path = names
+                .get(&entry.offset)
+                .cloned()
+                .unwrap_or_else(|| synthetic_name(entry.offset));
+            if !verified {
+                unverified.push(path.clone());
+            }
+            (path, entry)
+        })
+        .collect();
+
+    Ok(Recovery {
+        pak: PakReader::from_recovered(
+            version,
+            salvaged
+                .mount_point
+                .unwrap_or_else(|| "../../../".to_owned()),
+            salvaged.path_hash_seed,
+            compression,
+            entries,
+            index_offset,
+            key,
+            codecs,
+            encryption,
+        ),
+        unverified,
+    })
+}
+
+/// Whatever could be read from the indexes of a pak
+#[derive(Default)]
+struct Salvaged {
+    mount_point: Option<String>,
+    path_hash_seed: Option<u64>,
+    /// Paths by the offset of their entry
+    names: HashMap<u64, String>,
+}
+
+/// Reads the footer, the primary index and the full directory index of a pak which could not
+/// be read as a whole. Each part is parsed independently and entries are read one by one, so
+/// damage only loses the names which depend on the damaged bytes.
+fn salvage<R: Read + Seek>(
+    reader: &mut R,
+    len: u64,
+    version: Version,
+    #[allow(unused)] key: &Key,
+    codecs: &Codecs,
+    #[allow(unused)] encryption: &dyn EncryptionScheme,
+) -> Result<Salvaged> {
+    reader.seek(SeekFrom::End(-version.size()))?;
+    let footer = Footer::read(reader, version, codecs)?;
+    let mut read_index = |offset: u64, size: u64| -> Result<io::Cursor<Vec<u8>>> {
+        if offset.checked_add(size).is_none_or(|end| end > len) {
+            return Err(Error::Other(format!(
+                "index at {offset:#x} of size {size:#x} is out of bounds"
+            )));
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        #[allow(unused_mut)]
+        let mut index = reader.read_len(size as usize)?;
+        if footer.encrypted {
+            #[cfg(not(feature = "encryption"))]
+            return Err(Error::Encryption);
+            #[cfg(feature = "encryption")]
+            crate::data::decrypt_index(encryption, key, &mut index)?;
+        }
+        Ok(io::Cursor::new(index))
+    };
+
+    let mut index = read_index(footer.index_offset, footer.index_size)?;
+    let mut salvaged = Salvaged {
+        mount_point: Some(index.read_string()?),
+        ..Default::default()
+    };
+    let count = index.read_u32::<LE>()?;
+    if version.version_major() < VersionMajor::PathHashIndex {
+        for _ in 0..count {
+            let Ok(path) = index.read_string() else { break };
+            let Ok(entry) = Entry::read(&mut index, version) else {
+                break;
+            };
+            salvaged.names.insert(entry.offset, path);
+        }
+        return Ok(salvaged);
+    }
+
+    salvaged.path_hash_seed = Some(index.read_u64::<LE>()?);
+    if index.read_u32::<LE>()? != 0 {
+        index.seek(SeekFrom::Current(8 + 8 + 20))?;
+    }
+    if index.read_u32::<LE>()? == 0 {
+        // paths are only known by their hash
+        return Ok(salvaged);
+    }
+    let fdi_offset = index.read_u64::<LE>()?;
+    let fdi_size = index.read_u64::<LE>()?;
+    index.seek(SeekFrom::Current(20))?;
+
+    let mut files = vec![];
+    if let Ok(mut fdi) = read_index(fdi_offset, fdi_size) {
+        'fdi: for _ in 0..fdi.read_u32::<LE>().unwrap_or_default() {
+            let Ok(dir_name) = fdi.read_string() else {
+                break;
+            };
+            let Ok(file_count) = fdi.read_u32::<LE>() else {
+                break;
+            };
+            for _ in 0..file_count {
+                let (Ok(file_name), Ok(encoded_offset)) = (fdi.read_string(), fdi.read_u32::<LE>())
+                else {
+                    break 'fdi;
+                };
+                let dir_name = dir_name.strip_prefix('/').unwrap_or(&dir_name);
+                files.push((format!("{dir_name}{file_name}"), encoded_offset));
+            }
+        }
+    }
+
+    let remaining = |index: &io::Cursor<Vec<u8>>| index.get_ref().len() as u64 - index.position();
+    let encoded_entries = match index.read_u32::<LE>() {
+        Ok(size) if size as u64 <= remaining(&index) => index.read_len(size as usize)?,
+        _ => vec![],
+    };
+    let mut unencoded_entries = vec![];
+    for _ in 0..index.read_u32::<LE>().unwrap_or_default() {
+        let Ok(entry) = Entry::read(&mut index, version) else {
+            break;
+        };
+        unencoded_entries.push(entry);
+    }
+    let mut encoded_entries = io::Cursor::new(encoded_entries);
+    for (path, encoded_offset) in files {
+        let entry = match encoded_offset as i32 {
+            i32::MIN => None,
+            offset if offset < 0 => unencoded_entries.get((-(offset + 1)) as usize).cloned(),
+            offset => {
+                encoded_entries.set_position(offset as u64);
+                Entry::read_encoded(&mut encoded_entries, version).ok()
+            }
+        };
+        if let Some(entry) = entry {
+            salvaged.names.insert(entry.offset, path);
+        }
+    }
+    Ok(salvaged)
+}
+
+fn synthetic_name(offset: u64) -> String {
+    format!("recovered/{offset:012x}.bin")
+}
+
+fn data_end(entry: &Entry, version: Version) -> u64 {
+    let header = Entry::get_serialized_size(
+        version,
+        entry.compression_slot,
+        entry.blocks.as_ref().map_or(0, |b| b.len() as u32),
+    );
+    let size = match entry.is_encrypted() {
+        true => (entry.compressed + 15) & !15,
+        false => entry.compressed,
+    };
+    entry.offset + header + size
+}
+
+struct Scanner<'a, R> {
+    reader: &'a mut R,
+    len: u64,
+    version: Version,
+    key: &'a Key,
+    encryption: &'a dyn EncryptionScheme,
+    codecs: &'a Codecs,
+    names: &'a HashMap<u64, String>,
+    /// Whether `compression` holds the actual slots rather than guesses
+    known: bool,
+    compression: &'a mut Vec<Option<Compression>>,
+}
+
+impl<R: Read + Seek> Scanner<'_, R> {
+    fn scan(&mut self) -> Result<Vec<Found>> {
+        let mut found = vec![];
+        let mut pos = 0;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        'scan: while pos < self.len {
+            self.reader.seek(SeekFrom::Start(pos))?;
+            let size = (self.len - pos).min(CHUNK_SIZE as u64) as usize;
+            self.reader.read_exact(&mut chunk[..size])?;
+
+            // every header starts with a zeroed offset, so only the tail of each run of zeros
+            // needs to be checked
+            let mut run_start = pos;
+            for (i, &byte) in chunk[..size].iter().enumerate() {
+                let at = pos + i as u64;
+                let end_of_file = at + 1 == self.len;
+                if byte == 0 && !end_of_file {
+                    continue;
+                }
+                let run_end = if byte == 0 { at + 1 } else { at };
+                if run_end - run_start >= 8 {
+                    let first = run_start.max(run_end.saturating_sub(ZERO_PREFIX));
+                    for candidate in first..=run_end - 8 {
+                        if let Some(f) = self.try_entry(candidate)? {
+                            pos = data_end(&f.entry, self.version);
+                            found.push(f);
+                            continue 'scan;
+                        }
+                    }
+                }
+                run_start = at + 1;
+            }
+            // carry an unterminated run of zeros over into the next chunk
+            let next = pos + size as u64;
+            pos = if next < self.len {
+                run_start.min(next).max(next.saturating_sub(ZERO_PREFIX))
+            } else {
+                next
+            };
+        }
+        Ok(found)
+    }
+
+    fn try_entry(&mut self, pos: u64) -> Result<Option<Found>> {
+        let Some(mut entry) = self.read_header(pos)? else {
+            return Ok(None);
+        };
+        entry.offset = pos;
+        if data_end(&entry, self.version) > self.len {
+            return Ok(None);
+        }
+        let path = self.names.get(&pos).cloned();
+
+        let verified = match entry.compression_slot {
+            _ if entry.is_encrypted() && (path.is_none() || matches!(self.key, Key::None)) => false,
+            None if !entry.is_encrypted() => {
+                self.reader.seek(SeekFrom::Start(
+                    data_end(&entry, self.version) - entry.compressed,
+                ))?;
+                let data = crate::ext::ReadExt::read_len(self.reader, entry.compressed as usize)?;
+                if entry.hash != Some(crate::pak::hash(&data)) {
+                    return Ok(None);
+                }
+                true
+            }
+            Some(slot) if !self.known && self.compression[slot as usize].is_none() => {
+                // the registered codecs are tried when the compression slot names are unknown
+                let candidates: Vec<_> = self
+                    .codecs
+                    .names()
+                    .filter_map(|name| self.codecs.resolve(name))
+                    .collect();
+                let Some(codec) = candidates.into_iter().find(|codec| {
+                    let mut compression = self.compression.clone();
+                    compression[slot as usize] = Some(codec.clone());
+                    self.decompresses(&entry, &compression, path.as_deref())
+                }) else {
+                    return Ok(None);
+                };
+                self.compression[slot as usize] = Some(codec);
+                true
+            }
+            _ => {
+                let compression = self.compression.clone();
+                if !self.decompresses(&entry, &compression, path.as_deref()) {
+                    return Ok(None);
+                }
+                true
+            }
+        };
+        Ok(Some(Found { entry, verified }))
+    }
+
+    /// Trial decompression of the whole entry
+    fn decompresses(
+        &mut self,
+        entry: &Entry,
+        compression: &[Option<Compression>],
+        path: Option<&str>,
+    ) -> bool {
+        let mut counter = Counter(0);
+        entry
+            .read_file(
+                self.reader,
+                self.version,
+                compression,
+                self.codecs,
+                self.key,
+                self.encryption,
+                &mut counter,
+                path.unwrap_or_default(),
+            )
+            .is_ok()
+            && counter.0 == entry.uncompressed
+    }
+
+    /// Reads an inline entry header, rejecting anything which is implausible before the
+    /// variable length block list is allocated
+    fn read_header(&mut self, pos: u64) -> Result<Option<Entry>> {
+        let version = self.version;
+        let major = version.version_major();
+        self.reader.seek(SeekFrom::Start(pos))?;
+        let mut prefix = vec![
+            0;
+            Entry::get_serialized_size(version, Some(0), 0) as usize
+                - match major >= VersionMajor::CompressionEncryption {
+                    true => 5, // flags and block size follow the block list
+                    false => 0,
+                }
+        ];
+        if self.reader.read_exact(&mut prefix).is_err() {
+            return Ok(None);
+        }
+        let mut header = io::Cursor::new(&prefix);
+        let offset = header.read_u64::<LE>()?;
+        let compressed = header.read_u64::<LE>()?;
+        let uncompressed = header.read_u64::<LE>()?;
+        let compression = match version {
+            Version::V8A => header.read_u8()? as u32,
+            _ => header.read_u32::<LE>()?,
+        };
+        if major == VersionMajor::Initial {
+            header.read_u64::<LE>()?;
+        }
+        let mut hash = [0; 20];
+        header.read_exact(&mut hash)?;
+
+        if offset != 0
+            || compressed > self.len
+            || hash == [0; 20]
+            || compression as usize > self.compression.len()
+            || (compression == 0 && compressed != uncompressed)
+            || (compression != 0 && major < VersionMajor::CompressionEncryption)
+        {
+            return Ok(None);
+        }
+        if compression != 0 {
+            let block_count = header.read_u32::<LE>()? as u64;
+            if block_count == 0 || block_count > compressed {
+                return Ok(None);
+            }
+        }
+
+        self.reader.seek(SeekFrom::Start(pos))?;
+        let Ok(entry) = Entry::read(self.reader, version) else {
+            return Ok(None);
+        };
+        if entry.flags & !1 != 0 {
+            return Ok(None);
+        }
+        if let Some(blocks) = &entry.blocks {
+            let block_size = entry.compression_block_size;
+            let count = blocks.len() as u64;
+            if block_size == 0
+                || block_size > MAX_COMPRESSION_BLOCK_SIZE
+                || uncompressed > count * block_size as u64
+                || uncompressed <= (count - 1) * block_size as u64
+            {
+                return Ok(None);
+            }
+            let header_size =
+                Entry::get_serialized_size(version, entry.compression_slot, count as u32);
+            let mut expected = match major >= VersionMajor::RelativeChunkOffsets {
+                true => header_size,
+                false => pos + header_size,
+            };
+            for block in blocks {
+                if block.start != expected || block.end <= block.start {
+                    return Ok(None);
+                }
+                expected = match entry.is_encrypted() {
+                    true => block.start + ((block.end - block.start + 15) & !15),
+                    false => block.end,
+                };
+            }
+            if blocks.last().unwrap().end - blocks[0].start != compressed && !entry.is_encrypted() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(entry))
+    }
+}
+
+struct Counter(u64);
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
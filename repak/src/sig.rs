@@ -0,0 +1,190 @@
+//! Pak signature (`.sig`) files.
+//!
+//! A signature file holds a CRC32 for every 64KiB chunk of the pak and a SHA1 of those hashes
+//! (the master hash) encrypted with the private RSA key of the game.
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+
+use crate::{ext::ReadExt, Error};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+pub use rsa::{RsaPrivateKey as PrivateKey, RsaPublicKey as PublicKey};
+
+pub const MAGIC: u32 = 0x73832DAA;
+pub const VERSION: u32 = 1;
+/// Size of the pak chunks which are individually hashed
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// RSA encrypted SHA1 of `chunk_hashes`
+    pub encrypted_hash: Vec<u8>,
+    /// CRC32 of each chunk of the pak
+    pub chunk_hashes: Vec<u32>,
+}
+
+impl Signature {
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let magic = reader.read_u32::<LE>()?;
+        if magic != MAGIC {
+            return Err(Error::SignatureMagic(magic));
+        }
+        let version = reader.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(Error::Other(format!(
+                "unsupported signature version {version}"
+            )));
+        }
+        let len = reader.read_u32::<LE>()? as usize;
+        let encrypted_hash = reader.read_len(len)?;
+        let chunk_hashes = ReadExt::read_array(reader, |r| Ok(r.read_u32::<LE>()?))?;
+        Ok(Self {
+            encrypted_hash,
+            chunk_hashes,
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<LE>(MAGIC)?;
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(self.encrypted_hash.len() as u32)?;
+        writer.write_all(&self.encrypted_hash)?;
+        writer.write_u32::<LE>(self.chunk_hashes.len() as u32)?;
+        for hash in &self.chunk_hashes {
+            writer.write_u32::<LE>(*hash)?;
+        }
+        Ok(())
+    }
+
+    /// SHA1 of the chunk hashes which is signed
+    pub fn master_hash(&self) -> [u8; 20] {
+        use sha1::{Digest, Sha1};
+        let mut hasher = Sha1::new();
+        for hash in &self.chunk_hashes {
+            hasher.update(hash.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// Computes the CRC32 of every chunk of `pak`
+pub fn chunk_hashes<R: Read>(pak: &mut R) -> Result<Vec<u32>> {
+    let mut hashes = vec![];
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let mut len = 0;
+        while len < CHUNK_SIZE {
+            match pak.read(&mut chunk[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            break;
+        }
+        hashes.push(crc32fast::hash(&chunk[..len]));
+        if len < CHUNK_SIZE {
+            break;
+        }
+    }
+    Ok(hashes)
+}
+
+/// Generates the signature of `pak`
+pub fn generate<R: Read>(pak: &mut R, key: &RsaPrivateKey) -> Result<Signature> {
+    let mut signature = Signature {
+        encrypted_hash: vec![],
+        chunk_hashes: chunk_hashes(pak)?,
+    };
+    signature.encrypted_hash =
+        key.sign(Pkcs1v15Sign::new_unprefixed(), &signature.master_hash())?;
+    Ok(signature)
+}
+
+/// Verifies `pak` against its signature, failing on the first mismatching chunk
+pub fn verify<R: Read>(pak: &mut R, signature: &Signature, key: &RsaPublicKey) -> Result<()> {
+    key.verify(
+        Pkcs1v15Sign::new_unprefixed(),
+        &signature.master_hash(),
+        &signature.encrypted_hash,
+    )
+    .map_err(|_| Error::SignatureMismatch)?;
+
+    let hashes = chunk_hashes(pak)?;
+    if let Some(chunk) = hashes
+        .iter()
+        .zip(&signature.chunk_hashes)
+        .position(|(a, b)| a != b)
+    {
+        return Err(Error::ChunkHashMismatch(chunk));
+    }
+    if hashes.len() != signature.chunk_hashes.len() {
+        return Err(Error::ChunkHashMismatch(
+            hashes.len().min(signature.chunk_hashes.len()),
+        ));
+    }
+    Ok(())
+}
+
+/// Loads a private key from a PKCS#8 or PKCS#1 PEM file
+pub fn load_private_key<P: AsRef<Path>>(path: P) -> Result<RsaPrivateKey> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+    let pem = std::fs::read_to_string(&path)?;
+    RsaPrivateKey::from_pkcs8_pem(&pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+        .map_err(|err| Error::InvalidKey(format!("{}: {err}", path.as_ref().display())))
+}
+
+/// Loads a public key from a PKCS#8 (SubjectPublicKeyInfo) or PKCS#1 PEM file
+pub fn load_public_key<P: AsRef<Path>>(path: P) -> Result<RsaPublicKey> {
+    use rsa::pkcs1::DecodeRsaPublicKey;
+    use rsa::pkcs8::DecodePublicKey;
+    let pem = std::fs::read_to_string(&path)?;
+    RsaPublicKey::from_public_key_pem(&pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(&pem))
+        .map_err(|err| Error::InvalidKey(format!("{}: {err}", path.as_ref().display())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sig_key.pem");
+    const PUBLIC_KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sig_key.pub.pem");
+
+    #[test]
+    fn test_sign_verify() {
+        let key = load_private_key(KEY).unwrap();
+        let public_key = load_public_key(PUBLIC_KEY).unwrap();
+
+        let pak = include_bytes!("../tests/packs/pack_v11_compress.pak");
+        let mut pak = pak.repeat(CHUNK_SIZE * 2 / pak.len() + 1);
+        let signature = generate(&mut Cursor::new(&pak), &key).unwrap();
+        assert_eq!(signature.chunk_hashes.len(), pak.len().div_ceil(CHUNK_SIZE));
+
+        let mut buf = vec![];
+        signature.write(&mut buf).unwrap();
+        let signature = Signature::read(&mut Cursor::new(buf)).unwrap();
+        verify(&mut Cursor::new(&pak), &signature, &public_key).unwrap();
+
+        pak[CHUNK_SIZE + 1] ^= 0xff;
+        assert!(matches!(
+            verify(&mut Cursor::new(&pak), &signature, &public_key),
+            Err(Error::ChunkHashMismatch(1))
+        ));
+
+        let mut tampered = signature.clone();
+        tampered.chunk_hashes[0] ^= 1;
+        assert!(matches!(
+            verify(&mut Cursor::new(&pak), &tampered, &public_key),
+            Err(Error::SignatureMismatch)
+        ));
+    }
+}
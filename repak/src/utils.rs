@@ -20,4 +20,15 @@ impl std::str::FromStr for AesKey {
             })
             .ok_or(crate::Error::Aes)
     }
-}
\ No newline at end of file
+}
+
+/// Converts `time` to `FDateTime` ticks, the 100 ns intervals since 0001-01-01 used for entry
+/// timestamps of V1 paks
+pub fn ticks(time: std::time::SystemTime) -> u64 {
+    const UNIX_EPOCH_TICKS: i128 = 621_355_968_000_000_000;
+    let nanos = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    };
+    (UNIX_EPOCH_TICKS + nanos / 100).max(0) as u64
+}
@@ -106,6 +106,7 @@ fn test_read(version: repak::Version, _file_name: &str, bytes: &[u8]) {
 
     let pak = repak::PakBuilder::new()
         .key(key)
+        .encryption(repak::UnrealStandard)
         .reader(&mut reader)
         .unwrap();
 
@@ -159,7 +160,7 @@ fn test_read(version: repak::Version, _file_name: &str, bytes: &[u8]) {
     }
 }
 
-fn test_write(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
+fn test_write(_version: repak::Version, file_name: &str, bytes: &[u8]) {
     use aes::cipher::KeyInit;
     use base64::{engine::general_purpose, Engine as _};
     let key = general_purpose::STANDARD
@@ -171,24 +172,139 @@ fn test_write(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
 
     let mut reader = std::io::Cursor::new(bytes);
     let pak_reader = repak::PakBuilder::new()
-        .key(key)
+        .key(key.clone())
+        .encryption(repak::UnrealStandard)
         .reader(&mut reader)
         .unwrap();
 
-    let writer = Cursor::new(vec![]);
-    let mut pak_writer = repak::PakBuilder::new().writer(
-        writer,
+    let encrypt = file_name.contains("_encrypt.") || file_name.contains("_encrypt_");
+
+    // rebuild with the compression and encryption flags of each entry
+    let manifest = pak_reader.manifest();
+    let rebuilt = repak::PakBuilder::new()
+        .key(key.clone())
+        .encryption(repak::UnrealStandard)
+        .writer_from_manifest(Cursor::new(vec![]), &manifest, |path| {
+            pak_reader.get(path, &mut reader)
+        })
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert_eq!(rebuilt.len(), bytes.len());
+    assert_pak_eq(&pak_reader, &key, bytes, &rebuilt, "rebuilt", file_name);
+
+    // the regular writer selects the encrypted entries by path
+    let encrypted: std::collections::HashSet<String> = pak_reader
+        .entries()
+        .filter(|(_, entry)| entry.is_encrypted())
+        .map(|(path, _)| path.to_owned())
+        .collect();
+    assert_eq!(encrypt, !encrypted.is_empty());
+    let mut builder = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .encryption(repak::UnrealStandard)
+        .encrypt_index(pak_reader.encrypted_index())
+        .encrypt_entries(move |path| encrypted.contains(path));
+    if encrypt || pak_reader.encrypted_index() {
+        builder = builder.key(key.clone());
+    }
+    let mut pak_writer = builder.writer(
+        Cursor::new(vec![]),
         pak_reader.version(),
         pak_reader.mount_point().to_owned(),
-        Some(0x205C5A7D),
+        pak_reader.path_hash_seed(),
     );
 
     for path in pak_reader.files() {
         let data = pak_reader.get(&path, &mut reader).unwrap();
-        pak_writer.write_file(&path, false, data).unwrap();
+        let compress = pak_reader
+            .get_file_entry(&path)
+            .unwrap()
+            .compression_slot
+            .is_some();
+        pak_writer.write_file(&path, compress, data).unwrap();
     }
 
-    assert!(pak_writer.write_index().unwrap().into_inner() == reader.into_inner());
+    let written = pak_writer.write_index().unwrap().into_inner();
+    assert_pak_eq(&pak_reader, &key, bytes, &written, "written", file_name);
+}
+
+/// Compares a written pak with the original. UnrealPak for V5 pads uncompressed entries with
+/// uninitialized memory before encrypting them, so for those paks the last AES block of each
+/// encrypted entry and every hash covering it are masked out and all other bytes must match.
+fn assert_pak_eq(
+    pak_reader: &repak::PakReader,
+    key: &aes::Aes256,
+    expected: &[u8],
+    actual: &[u8],
+    what: &str,
+    file_name: &str,
+) {
+    use aes::cipher::BlockDecrypt;
+    use byteorder::LE;
+
+    let version = pak_reader.version();
+    let padded = pak_reader
+        .files()
+        .iter()
+        .map(|path| pak_reader.get_file_entry(path).unwrap())
+        .any(|entry| entry.is_encrypted() && entry.compression_slot.is_none());
+    if version != repak::Version::V5 || !padded {
+        assert!(actual == expected, "{file_name} {what} pak differs");
+        return;
+    }
+    assert_eq!(actual.len(), expected.len(), "{file_name} {what} pak size");
+
+    // V5 footer: encrypted flag, magic, version, index offset, index size, index hash
+    let footer = expected.len() - 45;
+    let mut cursor = Cursor::new(&expected[footer + 9..]);
+    let index_offset = cursor.read_u64::<LE>().unwrap() as usize;
+    let index_size = cursor.read_u64::<LE>().unwrap() as usize;
+    let index_range = index_offset..index_offset + index_size;
+
+    let mut expected = expected.to_vec();
+    let mut actual = actual.to_vec();
+    if pak_reader.encrypted_index() {
+        for buf in [&mut expected, &mut actual] {
+            for block in buf[index_range.clone()].chunks_mut(16) {
+                key.decrypt_block(aes::Block::from_mut_slice(block));
+            }
+        }
+    }
+
+    // inline entry headers and V5 index entries both store the hash 28 bytes in
+    let hash_offset = 8 + 8 + 8 + 4;
+    let mut masked = Vec::new();
+    // index hash at the end of the footer
+    masked.push(footer + 25..expected.len());
+    let mut index = Cursor::new(&expected[index_range.clone()]);
+    let mount_len = index.read_u32::<LE>().unwrap();
+    index.seek(SeekFrom::Current(mount_len as i64)).unwrap();
+    for _ in 0..index.read_u32::<LE>().unwrap() {
+        let path_len = index.read_u32::<LE>().unwrap();
+        index.seek(SeekFrom::Current(path_len as i64)).unwrap();
+        let start = index_offset + index.position() as usize;
+        let entry = repak::entry::Entry::read(&mut index, version).unwrap();
+        masked.push(start + hash_offset..start + hash_offset + 20);
+
+        let offset = entry.offset as usize;
+        masked.push(offset + hash_offset..offset + hash_offset + 20);
+        if entry.is_encrypted() && entry.compression_slot.is_none() {
+            let header = repak::entry::Entry::get_serialized_size(version, None, 0) as usize;
+            let end = offset + header + (entry.compressed as usize).next_multiple_of(16);
+            masked.push(end - 16..end);
+        }
+    }
+    for range in masked {
+        assert!(range.end <= expected.len());
+        expected[range.clone()].fill(0);
+        actual[range].fill(0);
+    }
+    assert!(
+        actual == expected,
+        "{file_name} {what} pak differs outside of the masked hashes and padding"
+    );
 }
 
 fn test_rewrite_index(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
@@ -202,7 +318,11 @@ fn test_rewrite_index(_version: repak::Version, _file_name: &str, bytes: &[u8])
         .unwrap();
 
     let mut buf = std::io::Cursor::new(bytes.to_vec());
-    let pak_reader = repak::PakBuilder::new().key(key).reader(&mut buf).unwrap();
+    let pak_reader = repak::PakBuilder::new()
+        .key(key)
+        .encryption(repak::UnrealStandard)
+        .reader(&mut buf)
+        .unwrap();
 
     let rewrite = pak_reader
         .into_pakwriter(buf)
@@ -214,6 +334,28 @@ fn test_rewrite_index(_version: repak::Version, _file_name: &str, bytes: &[u8])
     assert!(bytes == rewrite);
 }
 
+fn test_compact(_version: repak::Version, file_name: &str, bytes: &[u8]) {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key =
+        aes::Aes256::new_from_slice(&general_purpose::STANDARD.decode(AES_KEY).unwrap()).unwrap();
+
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new()
+        .key(key)
+        .encryption(repak::UnrealStandard)
+        .reader(&mut reader)
+        .unwrap();
+    let (compacted, reclaimed) = repak::compact(&pak, &mut reader, Cursor::new(vec![])).unwrap();
+
+    // paks written by UnrealPak have no dead space
+    assert_eq!(reclaimed, 0);
+    assert!(
+        compacted.into_inner() == bytes,
+        "{file_name} compacted pak differs"
+    );
+}
+
 macro_rules! matrix_test {
     ( $name:literal, ($($version:literal $exp_version:expr),* $(,)?), $compress:tt, $encrypt:tt, $encryptindex:tt, $body:tt ) => {
         $( matrix_test_compress!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
@@ -278,9 +420,9 @@ matrix_test!(
         "v9" repak::Version::V9,
         "v11" repak::Version::V11,
     ),
-    ("", /*"_compress"*/),
-    ("", /*"_encrypt"*/),
-    ("", /*"_encryptindex"*/),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", "_encryptindex"),
     test_write
 );
 
@@ -296,6 +438,1342 @@ matrix_test!(
     ),
     ("", "_compress"),
     ("", "_encrypt"),
-    ("", /*"_encryptindex"*/),
+    ("", "_encryptindex"),
     test_rewrite_index
 );
+
+matrix_test!(
+    "compact",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", "_encryptindex"),
+    test_compact
+);
+
+fn build_pak(version: repak::Version, files: &[(&str, bool, &[u8])]) -> Vec<u8> {
+    let mut pak_writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            version,
+            "../../../".to_owned(),
+            Some(0x205C5A7D),
+        );
+    for (path, compress, data) in files {
+        pak_writer.write_file(path, *compress, data).unwrap();
+    }
+    pak_writer.write_index().unwrap().into_inner()
+}
+
+#[test]
+fn test_diff() {
+    let a = build_pak(
+        repak::Version::V11,
+        &[
+            ("SB/Content/same.txt", false, b"unchanged"),
+            ("SB/Content/modified.txt", false, b"before"),
+            ("SB/Content/recompressed.txt", false, b"same content"),
+            ("SB/Content/removed.txt", false, b"gone"),
+        ],
+    );
+    let b = build_pak(
+        repak::Version::V9,
+        &[
+            ("SB/Content/same.txt", false, b"unchanged"),
+            ("SB/Content/modified.txt", false, b"after!"),
+            ("SB/Content/recompressed.txt", true, b"same content"),
+            ("SB/Content/added.txt", false, b"new"),
+        ],
+    );
+
+    let mut reader_a = Cursor::new(a);
+    let mut reader_b = Cursor::new(b);
+    let pak_a = repak::PakBuilder::new().reader(&mut reader_a).unwrap();
+    let pak_b = repak::PakBuilder::new().reader(&mut reader_b).unwrap();
+
+    let diff = repak::diff(&pak_a, &mut reader_a, &pak_b, &mut reader_b).unwrap();
+    assert_eq!(diff.mount_point, None);
+    assert_eq!(
+        diff.version,
+        Some((repak::Version::V11, repak::Version::V9))
+    );
+    assert_eq!(diff.added, vec!["SB/Content/added.txt"]);
+    assert_eq!(diff.removed, vec!["SB/Content/removed.txt"]);
+    assert_eq!(diff.modified.len(), 2);
+
+    let modified = &diff.modified[0];
+    assert_eq!(modified.path, "SB/Content/modified.txt");
+    assert!(modified.content);
+    assert!(modified.metadata.is_empty());
+
+    let recompressed = &diff.modified[1];
+    assert_eq!(recompressed.path, "SB/Content/recompressed.txt");
+    assert!(!recompressed.content);
+    assert!(recompressed
+        .metadata
+        .contains(&repak::MetadataChange::Compression(
+            None,
+            Some(repak::Compression::Zlib)
+        )));
+
+    let mut reader = Cursor::new(reader_a.get_ref().clone());
+    let same = repak::diff(&pak_a, &mut reader_a, &pak_a, &mut reader).unwrap();
+    assert!(same.is_empty());
+}
+
+fn test_make_patch_version(version: repak::Version) {
+    let base = build_pak(
+        version,
+        &[
+            ("SB/Content/same.txt", false, b"unchanged"),
+            ("SB/Content/modified.txt", true, b"before"),
+            ("SB/Content/removed.txt", false, b"gone"),
+        ],
+    );
+    let new = build_pak(
+        version,
+        &[
+            ("SB/Content/same.txt", false, b"unchanged"),
+            ("SB/Content/modified.txt", true, b"after!"),
+            ("SB/Content/added.txt", false, b"new"),
+        ],
+    );
+
+    let mut base_reader = Cursor::new(base);
+    let mut new_reader = Cursor::new(new);
+    let base_pak = repak::PakBuilder::new().reader(&mut base_reader).unwrap();
+    let new_pak = repak::PakBuilder::new().reader(&mut new_reader).unwrap();
+
+    let patch = repak::make_patch(
+        &base_pak,
+        &mut base_reader,
+        &new_pak,
+        &mut new_reader,
+        Cursor::new(vec![]),
+    )
+    .unwrap()
+    .into_inner();
+
+    let mut patch_reader = Cursor::new(patch);
+    let patch_pak = repak::PakBuilder::new().reader(&mut patch_reader).unwrap();
+    assert_eq!(patch_pak.version(), version);
+
+    let deletes = version.version_major() >= repak::VersionMajor::DeleteRecords;
+    let mut expected = vec!["SB/Content/added.txt", "SB/Content/modified.txt"];
+    if deletes {
+        expected.push("SB/Content/removed.txt");
+    }
+    assert_eq!(patch_pak.files(), expected);
+
+    assert_eq!(
+        patch_pak
+            .get("SB/Content/modified.txt", &mut patch_reader)
+            .unwrap(),
+        b"after!"
+    );
+    assert_eq!(
+        patch_pak
+            .get("SB/Content/added.txt", &mut patch_reader)
+            .unwrap(),
+        b"new"
+    );
+    let modified = patch_pak.get_file_entry("SB/Content/modified.txt").unwrap();
+    assert_eq!(
+        patch_pak.entry_compression(&modified),
+        Some(repak::Compression::Zlib)
+    );
+    if deletes {
+        let removed = patch_pak.get_file_entry("SB/Content/removed.txt").unwrap();
+        assert!(removed.is_deleted());
+    }
+}
+
+#[test]
+fn test_make_patch() {
+    for version in [
+        repak::Version::V5,
+        repak::Version::V8B,
+        repak::Version::V9,
+        repak::Version::V11,
+    ] {
+        test_make_patch_version(version);
+    }
+}
+
+#[test]
+fn test_manifest_rebuild() {
+    for version in [repak::Version::V5, repak::Version::V9, repak::Version::V11] {
+        let bytes = build_pak(
+            version,
+            &[
+                ("SB/Content/b.txt", false, b"stored"),
+                ("SB/Content/a.txt", true, &[7; 0x20000]),
+                ("SB/Content/dir/c.txt", true, b"compressed"),
+            ],
+        );
+        let mut reader = Cursor::new(bytes.clone());
+        let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let manifest = pak_reader.manifest();
+        assert_eq!(manifest.entries.len(), 3);
+
+        let rebuilt = repak::PakBuilder::new()
+            .writer_from_manifest(Cursor::new(vec![]), &manifest, |path| {
+                pak_reader.get(path, &mut reader)
+            })
+            .unwrap()
+            .write_index()
+            .unwrap()
+            .into_inner();
+        assert!(rebuilt == bytes, "{version} rebuilt pak differs");
+    }
+}
+
+#[test]
+fn test_timestamps_frozen_index() {
+    let rebuild = |bytes: Vec<u8>, frozen_index: bool| {
+        let mut reader = Cursor::new(bytes);
+        let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let mut manifest = pak_reader.manifest();
+        manifest.frozen_index = frozen_index;
+        repak::PakBuilder::new()
+            .writer_from_manifest(Cursor::new(vec![]), &manifest, |path| {
+                pak_reader.get(path, &mut reader)
+            })
+            .unwrap()
+            .write_index()
+            .unwrap()
+            .into_inner()
+    };
+
+    // V1 entries keep their timestamps
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    let timestamp = repak::utils::ticks(time);
+    assert_eq!(timestamp, 638_355_968_000_000_000);
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V1,
+        "../../../".to_owned(),
+        None,
+    );
+    let entry = pak_writer
+        .entry_builder()
+        .build_entry(false, b"timestamped", "SB/Content/a.txt")
+        .unwrap()
+        .with_timestamp(timestamp);
+    pak_writer
+        .write_entry("SB/Content/a.txt".to_owned(), entry)
+        .unwrap();
+    pak_writer
+        .write_file("SB/Content/b.txt", false, b"plain")
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    let rebuilt = rebuild(bytes.clone(), false);
+    assert!(rebuilt == bytes, "V1 rebuilt pak differs");
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(rebuilt))
+        .unwrap();
+    assert_eq!(
+        pak.get_file_entry("SB/Content/a.txt").unwrap().timestamp,
+        Some(timestamp)
+    );
+    assert_eq!(
+        pak.get_file_entry("SB/Content/b.txt").unwrap().timestamp,
+        Some(0)
+    );
+
+    // V9 footers keep the frozen index flag
+    let bytes = include_bytes!("packs/pack_v9_compress.pak").to_vec();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    assert!(!pak.frozen_index());
+    let frozen = rebuild(bytes.clone(), true);
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&frozen))
+        .unwrap();
+    assert!(pak.frozen_index());
+    assert!(rebuild(frozen.clone(), true) == frozen);
+    assert!(rebuild(frozen, false) == rebuild(bytes, false));
+
+    let bytes = repak::PakBuilder::new()
+        .frozen_index(true)
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V9,
+            "../../../".to_owned(),
+            None,
+        )
+        .write_index()
+        .unwrap()
+        .into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(bytes))
+        .unwrap();
+    assert!(pak.frozen_index());
+}
+
+#[test]
+fn test_secondary_indexes() {
+    let write = |path_hash_index: bool, full_directory_index: bool| {
+        let mut pak_writer = repak::PakBuilder::new()
+            .path_hash_index(path_hash_index)
+            .full_directory_index(full_directory_index)
+            .writer(
+                Cursor::new(vec![]),
+                repak::Version::V11,
+                "../../../".to_owned(),
+                Some(0x205C5A7D),
+            );
+        pak_writer
+            .write_file("SB/Content/a.txt", false, b"first")
+            .unwrap();
+        pak_writer
+            .write_file("SB/Content/Dir/b.txt", false, b"second")
+            .unwrap();
+        pak_writer.write_index().map(Cursor::into_inner)
+    };
+
+    let full = write(true, true).unwrap();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&full))
+        .unwrap();
+    assert!(pak.has_path_hash_index() && pak.has_full_directory_index());
+
+    // without a path hash index the entries are still found by name
+    let bytes = write(false, true).unwrap();
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert!(!pak.has_path_hash_index() && pak.has_full_directory_index());
+    assert_eq!(pak.files(), ["SB/Content/Dir/b.txt", "SB/Content/a.txt"]);
+    assert_eq!(pak.get("SB/Content/a.txt", &mut reader).unwrap(), b"first");
+    let blobs = pak.index_blobs(&mut reader).unwrap();
+    assert!(blobs.path_hash_index.is_none() && blobs.full_directory_index.is_some());
+
+    // without a directory index only the path hashes are known
+    let bytes = write(true, false).unwrap();
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert!(pak.has_path_hash_index() && !pak.has_full_directory_index());
+    assert!(pak.files().iter().all(|path| path.starts_with("hashed/")));
+    assert_eq!(pak.files().len(), 2);
+    assert_eq!(
+        pak.get("../../../sb/content/dir/B.txt", &mut reader)
+            .unwrap(),
+        b"second"
+    );
+    assert_eq!(pak.get("SB/Content/a.txt", &mut reader).unwrap(), b"first");
+    assert!(pak.get("SB/Content/c.txt", &mut reader).is_err());
+
+    // the path hashes survive rewriting the index
+    let rewritten = pak
+        .into_pakwriter(Cursor::new(bytes.clone()))
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert!(rewritten == bytes, "rewritten pak differs");
+
+    // as does rebuilding from the manifest
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    let manifest = pak.manifest();
+    assert!(manifest.path_hash_index && !manifest.full_directory_index);
+    let mut reader = Cursor::new(&bytes);
+    let rebuilt = repak::PakBuilder::new()
+        .writer_from_manifest(Cursor::new(vec![]), &manifest, |path| {
+            pak.get(path, &mut reader)
+        })
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert!(rebuilt == bytes, "rebuilt pak differs");
+
+    assert!(matches!(write(false, false), Err(repak::Error::Other(_))));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_manifest_serde() {
+    let bytes = build_pak(
+        repak::Version::V11,
+        &[
+            ("SB/Content/a.txt", true, b"compressed"),
+            ("SB/Content/b.txt", false, b"stored"),
+        ],
+    );
+    let mut reader = Cursor::new(bytes);
+    let manifest = repak::PakBuilder::new()
+        .reader(&mut reader)
+        .unwrap()
+        .manifest();
+
+    let json = serde_json::to_string(&manifest).unwrap();
+    let parsed: repak::PakManifest = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    assert_eq!(parsed.version, repak::Version::V11);
+    assert_eq!(parsed.compression, manifest.compression);
+    assert_eq!(
+        parsed.entries["SB/Content/a.txt"].compression_slot,
+        manifest.entries["SB/Content/a.txt"].compression_slot
+    );
+}
+
+#[test]
+fn test_index_queries() {
+    fn names(children: Vec<repak::DirChild>) -> Vec<String> {
+        children
+            .into_iter()
+            .map(|child| match child {
+                repak::DirChild::Directory(path) => path.to_owned(),
+                repak::DirChild::File(path, _) => path.to_owned(),
+            })
+            .collect()
+    }
+
+    for version in [repak::Version::V9, repak::Version::V11] {
+        let bytes = build_pak(
+            version,
+            &[
+                ("SB/Content/Art/a.uasset", false, b"a"),
+                ("SB/Content/Art/a.uexp", false, b"a"),
+                ("SB/Content/Art/Sub/b.uasset", false, b"b"),
+                ("SB/Content/UI/c.uasset", false, b"c"),
+                ("SB/Content-Other/d.uasset", false, b"d"),
+                ("root.txt", false, b"root"),
+            ],
+        );
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+
+        assert_eq!(
+            pak.entries().map(|(path, _)| path).collect::<Vec<_>>(),
+            pak.files()
+        );
+        assert_eq!(names(pak.list_dir("/")), vec!["SB/", "root.txt"]);
+        assert_eq!(
+            names(pak.list_dir("SB")),
+            vec!["SB/Content/", "SB/Content-Other/"]
+        );
+        assert_eq!(
+            names(pak.list_dir("SB/Content/Art/")),
+            vec![
+                "SB/Content/Art/Sub/",
+                "SB/Content/Art/a.uasset",
+                "SB/Content/Art/a.uexp"
+            ]
+        );
+        assert!(pak.list_dir("missing").is_empty());
+        assert_eq!(
+            pak.walk("SB/Content")
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            vec![
+                "SB/Content/Art/Sub/b.uasset",
+                "SB/Content/Art/a.uasset",
+                "SB/Content/Art/a.uexp",
+                "SB/Content/UI/c.uasset"
+            ]
+        );
+        assert_eq!(pak.walk("").count(), 6);
+        assert_eq!(
+            pak.glob("SB/Content/**/*.uasset")
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            vec![
+                "SB/Content/Art/Sub/b.uasset",
+                "SB/Content/Art/a.uasset",
+                "SB/Content/UI/c.uasset"
+            ]
+        );
+    }
+
+    // directory index as written by UnrealPak
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(include_bytes!("packs/pack_v11.pak")))
+        .unwrap();
+    assert_eq!(
+        names(pak.list_dir("")),
+        vec!["directory/", "test.png", "test.txt", "zeros.bin"]
+    );
+    assert_eq!(
+        names(pak.list_dir("directory")),
+        vec!["directory/nested.txt"]
+    );
+}
+
+#[test]
+fn test_path_lookup() {
+    for (mount_point, path) in [
+        ("../../../", "SB/Content/Art/Hero.uasset"),
+        ("../../../SB/Content/", "Art/Hero.uasset"),
+    ] {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            mount_point.to_owned(),
+            Some(0x205C5A7D),
+        );
+        pak_writer.write_file(path, false, b"hero").unwrap();
+        let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+
+        for query in [
+            path.to_owned(),
+            path.to_lowercase(),
+            path.to_uppercase().replace('/', "\\"),
+            path.replace('/', "//"),
+            "../../../SB/Content/Art/Hero.uasset".to_owned(),
+            "/sb/content/art/hero.uasset".to_owned(),
+            "/Game/Art/Hero.uasset".to_owned(),
+        ] {
+            assert_eq!(pak.resolve(&query), Some(path), "{mount_point} {query}");
+            assert_eq!(pak.get(&query, &mut reader).unwrap(), b"hero");
+        }
+        assert_eq!(pak.resolve("Art/Villain.uasset"), None);
+        assert_eq!(pak.resolve("/Game/Hero.uasset"), None);
+        assert!(matches!(
+            pak.get("/Game/Art/Villain.uasset", &mut reader),
+            Err(repak::Error::MissingEntry(_))
+        ));
+    }
+}
+
+#[test]
+fn test_mount_points() {
+    for mount_point in [
+        "../../../",
+        "../../../SB/Content/",
+        "../../../SB/",
+        "/Game/",
+        "../mount/point/root/",
+    ] {
+        let mut pak_writer = repak::PakBuilder::new()
+            .compression([repak::Compression::Zlib])
+            .writer(
+                Cursor::new(vec![]),
+                repak::Version::V11,
+                mount_point.to_owned(),
+                Some(0x205C5A7D),
+            );
+        pak_writer
+            .write_file("Art/a.uasset", true, b"compressed")
+            .unwrap();
+        let partial = pak_writer
+            .entry_builder()
+            .build_entry(false, b"built".to_vec(), "Art/b.uasset")
+            .unwrap();
+        pak_writer
+            .write_entry("Art/b.uasset".to_owned(), partial)
+            .unwrap();
+
+        let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(pak.mount_point(), mount_point);
+        assert_eq!(pak.get("Art/a.uasset", &mut reader).unwrap(), b"compressed");
+        assert_eq!(pak.get("Art/b.uasset", &mut reader).unwrap(), b"built");
+    }
+}
+
+#[test]
+fn test_recover() {
+    let large: Vec<u8> = (0..0x30000u32).map(|i| (i % 251) as u8).collect();
+    let zeros = vec![0; 300];
+    let files: &[(&str, bool, &[u8])] = &[
+        ("SB/Content/stored.txt", false, b"stored"),
+        (
+            "SB/Content/compressed.txt",
+            true,
+            b"compressed compressed compressed",
+        ),
+        ("SB/Content/zeros.bin", false, &zeros),
+        ("SB/Content/large.bin", true, &large),
+        ("SB/Content/last.txt", false, b"last"),
+    ];
+    for version in [repak::Version::V5, repak::Version::V8B, repak::Version::V11] {
+        let bytes = build_pak(version, files);
+
+        // intact index: names and version are taken from the index
+        let recovery = repak::recover(&mut Cursor::new(&bytes)).unwrap();
+        assert!(recovery.unverified.is_empty());
+        assert_eq!(recovery.pak.version(), version);
+        let mut reader = Cursor::new(&bytes);
+        for (path, _, data) in files {
+            assert_eq!(&recovery.pak.get(path, &mut reader).unwrap(), data);
+        }
+
+        // damaged footer: entries are recovered under synthetic names in order
+        let truncated = bytes[..bytes.len() - 100].to_vec();
+        assert!(repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&truncated))
+            .is_err());
+        let recovery = repak::recover(&mut Cursor::new(&truncated)).unwrap();
+        assert!(recovery.unverified.is_empty());
+        let mut reader = Cursor::new(&truncated);
+        let recovered = recovery.pak.files();
+        assert_eq!(recovered.len(), files.len());
+        for (path, (_, _, data)) in recovered.iter().zip(files) {
+            assert!(path.starts_with("recovered/"));
+            assert_eq!(&recovery.pak.get(path, &mut reader).unwrap(), data);
+        }
+
+        // damaged primary index: names are salvaged from the entries and directory index
+        let index = bytes
+            .windows(14)
+            .position(|w| w == b"\x0a\0\0\0../../../\0")
+            .unwrap();
+        let count = match version >= repak::Version::V10 {
+            true => {
+                // unencoded entries follow the encoded entries at the end of the primary index
+                let encoded = index + 14 + 4 + 8 + 40 + 40;
+                let size = u32::from_le_bytes(bytes[encoded..encoded + 4].try_into().unwrap());
+                encoded + 4 + size as usize
+            }
+            false => index + 14,
+        };
+        let mut damaged = bytes.clone();
+        damaged[count..count + 4].copy_from_slice(&(files.len() as u32 + 1).to_le_bytes());
+        assert!(repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&damaged))
+            .is_err());
+        let recovery = repak::recover(&mut Cursor::new(&damaged)).unwrap();
+        assert!(recovery.unverified.is_empty());
+        assert_eq!(
+            recovery.pak.path_hash_seed(),
+            (version >= repak::Version::V10).then_some(0x205C5A7D)
+        );
+        let mut reader = Cursor::new(&damaged);
+        assert_eq!(recovery.pak.files().len(), files.len());
+        for (path, _, data) in files {
+            assert_eq!(&recovery.pak.get(path, &mut reader).unwrap(), data);
+        }
+    }
+}
+
+#[test]
+fn test_read_file_per_block() {
+    /// Records the data and the largest single write
+    struct Writes(Vec<u8>, usize);
+    impl io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1 = self.1.max(buf.len());
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let large: Vec<u8> = (0..0x30000u32).map(|i| (i % 251) as u8).collect();
+    let bytes = build_pak(
+        repak::Version::V11,
+        &[("SB/Content/large.bin", true, &large)],
+    );
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut writes = Writes(vec![], 0);
+    pak.read_file("SB/Content/large.bin", &mut reader, &mut writes)
+        .unwrap();
+    assert_eq!(writes.0, large);
+    // each block is written as soon as it is decompressed
+    assert_eq!(writes.1, 0x10000);
+}
+
+#[test]
+fn test_alignment() {
+    let large: Vec<u8> = (0..0x30000u32).map(|i| (i % 251) as u8).collect();
+    let files: &[(&str, bool, &[u8])] = &[
+        ("SB/Content/a.txt", false, b"stored"),
+        (
+            "SB/Content/b.txt",
+            true,
+            b"compressed compressed compressed",
+        ),
+        ("SB/Content/large.bin", true, &large),
+        ("SB/Content/c.txt", false, b"last"),
+    ];
+    for version in [repak::Version::V3, repak::Version::V8B, repak::Version::V11] {
+        let mut pak_writer = repak::PakBuilder::new()
+            .compression([repak::Compression::Zlib])
+            .alignment(0x800)
+            .writer(
+                Cursor::new(vec![]),
+                version,
+                "../../../".to_owned(),
+                Some(0x205C5A7D),
+            );
+        for (path, compress, data) in files {
+            pak_writer.write_file(path, *compress, data).unwrap();
+        }
+
+        let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for (path, _, data) in files {
+            let entry = pak.get_file_entry(path).unwrap();
+            let header = repak::entry::Entry::get_serialized_size(
+                version,
+                entry.compression_slot,
+                entry.blocks.as_ref().map_or(0, |b| b.len() as u32),
+            );
+            assert_eq!((entry.offset + header) % 0x800, 0, "{path} in {version}");
+            assert_eq!(&pak.get(path, &mut reader).unwrap(), data);
+        }
+    }
+}
+
+/// Trivial codec storing each byte xored with a constant
+struct Xor;
+impl repak::Codec for Xor {
+    fn name(&self) -> &str {
+        "Xor"
+    }
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, repak::Error> {
+        Ok(data.iter().map(|b| b ^ 0x5a).collect())
+    }
+    fn decompress(&self, data: &[u8], output: &mut [u8]) -> Result<(), repak::Error> {
+        for (out, b) in output.iter_mut().zip(data) {
+            *out = b ^ 0x5a;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_codec() {
+    let large: Vec<u8> = (0..0x30000u32).map(|i| (i % 251) as u8).collect();
+    let custom = repak::Compression::Custom("Xor".to_owned());
+    for version in [repak::Version::V8B, repak::Version::V11] {
+        let mut pak_writer = repak::PakBuilder::new()
+            .codec(Xor)
+            .compression([custom.clone()])
+            .writer(
+                Cursor::new(vec![]),
+                version,
+                "../../../".to_owned(),
+                Some(0x205C5A7D),
+            );
+        pak_writer
+            .write_file("SB/Content/large.bin", true, &large)
+            .unwrap();
+        pak_writer
+            .write_file("SB/Content/a.txt", false, b"stored")
+            .unwrap();
+        let bytes = pak_writer.write_index().unwrap().into_inner();
+
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new()
+            .codec(Xor)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(pak.compression()[0], Some(custom.clone()));
+        let entry = pak.get_file_entry("SB/Content/large.bin").unwrap();
+        assert_eq!(pak.entry_compression(&entry), Some(custom.clone()));
+        assert_eq!(pak.get("SB/Content/large.bin", &mut reader).unwrap(), large);
+
+        // the name of an unregistered codec is kept but entries using it cannot be read
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let unknown = repak::Compression::Unknown("Xor".to_owned());
+        assert_eq!(pak.compression()[0], Some(unknown));
+        assert!(matches!(
+            pak.get("SB/Content/large.bin", &mut reader),
+            Err(repak::Error::UnknownCompression(name)) if name == "Xor"
+        ));
+        assert_eq!(pak.get("SB/Content/a.txt", &mut reader).unwrap(), b"stored");
+
+        // and written back unchanged
+        let bytes = reader.into_inner();
+        let rewritten = pak
+            .into_pakwriter(Cursor::new(bytes.clone()))
+            .unwrap()
+            .write_index()
+            .unwrap()
+            .into_inner();
+        assert_eq!(rewritten, bytes);
+
+        // an entry referencing an unset slot is an error rather than read as stored
+        let mut cleared = bytes.clone();
+        let names = cleared.len() - 32 * 5 + usize::from(version == repak::Version::V8A) * 32;
+        cleared[names..names + 32].fill(0);
+        let mut reader = Cursor::new(cleared);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert!(matches!(
+            pak.get("SB/Content/large.bin", &mut reader),
+            Err(repak::Error::MissingCompressionSlot(0))
+        ));
+    }
+}
+
+#[test]
+fn test_profile() {
+    let mut profile = repak::GameProfile::unreal(repak::Version::V11);
+    profile.mount_point = "../../../SB/".to_owned();
+    profile.path_hash_seed = Some(0x205C5A7D);
+    let mut pak_writer = repak::PakBuilder::new().writer_for_profile(Cursor::new(vec![]), &profile);
+    let data = b"compressed compressed compressed".repeat(16);
+    pak_writer
+        .write_file("SB/Content/a.txt", true, &data)
+        .unwrap();
+
+    let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+    let pak = repak::PakBuilder::new()
+        .profile(&profile)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.version(), profile.version);
+    assert_eq!(pak.mount_point(), profile.mount_point);
+    assert_eq!(pak.path_hash_seed(), profile.path_hash_seed);
+    assert_eq!(pak.compression()[0], Some(repak::Compression::Zlib));
+    assert_eq!(pak.get("SB/Content/a.txt", &mut reader).unwrap(), data);
+}
+
+#[test]
+fn test_encryption_scheme() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key =
+        aes::Aes256::new_from_slice(&general_purpose::STANDARD.decode(AES_KEY).unwrap()).unwrap();
+
+    let large: Vec<u8> = (0..0x3000u32).map(|i| (i % 251) as u8).collect();
+    let mut pak_writer = repak::PakBuilder::new()
+        .key(key.clone())
+        .encryption(repak::StellarBladePartial)
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            Some(0x205C5A7D),
+        );
+    pak_writer
+        .write_file("SB/Content/large.bin", false, &large)
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    // only a prefix of the entry is encrypted
+    let tail = &large[0x1000..];
+    assert!(bytes.windows(tail.len()).any(|w| w == tail));
+
+    // Stellar Blade encryption is the default scheme for paks
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new()
+        .key(key.clone())
+        .reader(&mut reader)
+        .unwrap();
+    assert!(pak.encrypted_index());
+    assert_eq!(pak.get("SB/Content/large.bin", &mut reader).unwrap(), large);
+
+    // the index cannot be read with the standard scheme
+    assert!(repak::PakBuilder::new()
+        .key(key)
+        .encryption(repak::UnrealStandard)
+        .reader(&mut reader)
+        .is_err());
+}
+
+#[test]
+fn test_rename_replace() {
+    for version in [repak::Version::V5, repak::Version::V8B, repak::Version::V11] {
+        let bytes = build_pak(
+            version,
+            &[
+                ("SB/Content/Old/long_file_name.txt", false, b"renamed"),
+                ("SB/Content/b.txt", true, b"compressed before"),
+                ("SB/Content/c.txt", false, b"stored"),
+            ],
+        );
+        // a shorter path shrinks the index, which is padded to keep the footer at the end
+        let mut pak_writer = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap()
+            .into_pakwriter(Cursor::new(bytes.clone()))
+            .unwrap();
+        pak_writer
+            .rename_entry("SB/Content/Old/long_file_name.txt", "SB/Content/a.txt")
+            .unwrap();
+        let renamed = pak_writer.write_index().unwrap().into_inner();
+        assert_eq!(renamed.len(), bytes.len());
+        let mut reader = Cursor::new(&renamed);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.get("SB/Content/a.txt", &mut reader).unwrap(),
+            b"renamed"
+        );
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let old_b = pak.get_file_entry("SB/Content/b.txt").unwrap();
+        let old_b_region = pak
+            .layout(&mut reader)
+            .unwrap()
+            .regions
+            .iter()
+            .filter(|region| region.path.as_deref() == Some("SB/Content/b.txt"))
+            .map(|region| region.offset..region.offset + region.size)
+            .reduce(|a, b| a.start..b.end)
+            .unwrap();
+
+        let mut pak_writer = pak.into_pakwriter(Cursor::new(bytes.clone())).unwrap();
+        assert!(matches!(
+            pak_writer.rename_entry("SB/Content/missing.txt", "SB/Content/d.txt"),
+            Err(repak::Error::MissingEntry(_))
+        ));
+        assert!(matches!(
+            pak_writer.rename_entry("SB/Content/c.txt", "sb/content/B.txt"),
+            Err(repak::Error::DuplicateEntry(_))
+        ));
+        pak_writer
+            .rename_entry("sb/content/old/LONG_FILE_NAME.txt", "SB/Content/a.txt")
+            .unwrap();
+        pak_writer
+            .replace_entry("SB/Content/b.txt", b"compressed after, and longer")
+            .unwrap();
+        assert_eq!(pak_writer.orphaned(), [old_b_region]);
+        let rewritten = pak_writer.write_index().unwrap().into_inner();
+
+        let mut reader = Cursor::new(&rewritten);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.files(),
+            ["SB/Content/a.txt", "SB/Content/b.txt", "SB/Content/c.txt"]
+        );
+        assert!(pak
+            .list_dir("SB/Content")
+            .iter()
+            .all(|child| matches!(child, repak::DirChild::File(..))));
+        assert_eq!(
+            pak.get("SB/Content/a.txt", &mut reader).unwrap(),
+            b"renamed"
+        );
+        assert_eq!(
+            pak.get("SB/Content/b.txt", &mut reader).unwrap(),
+            b"compressed after, and longer"
+        );
+        assert_eq!(pak.get("SB/Content/c.txt", &mut reader).unwrap(), b"stored");
+        let new_b = pak.get_file_entry("SB/Content/b.txt").unwrap();
+        assert_eq!(new_b.compression_slot, old_b.compression_slot);
+        assert!(new_b.offset > old_b.offset);
+    }
+
+    // entries whose encrypted prefix depends on the path cannot be renamed in place
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key =
+        aes::Aes256::new_from_slice(&general_purpose::STANDARD.decode(AES_KEY).unwrap()).unwrap();
+    let builder = || {
+        repak::PakBuilder::new()
+            .key(key.clone())
+            .encryption(repak::StellarBladePartial)
+    };
+    let large: Vec<u8> = (0..0x3000u32).map(|i| (i % 251) as u8).collect();
+    let mut pak_writer = builder().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        Some(0x205C5A7D),
+    );
+    pak_writer
+        .write_file("SB/Content/large.bin", false, &large)
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+    let pak = builder().reader(&mut Cursor::new(&bytes)).unwrap();
+    let mut pak_writer = pak.into_pakwriter(Cursor::new(bytes)).unwrap();
+    assert!(matches!(
+        pak_writer.rename_entry("SB/Content/large.bin", "SB/Content/other.bin"),
+        Err(repak::Error::Other(_))
+    ));
+    pak_writer
+        .replace_entry("SB/Content/large.bin", &large[..0x2000])
+        .unwrap();
+    let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+    let pak = builder().reader(&mut reader).unwrap();
+    assert!(pak
+        .get_file_entry("SB/Content/large.bin")
+        .unwrap()
+        .is_encrypted());
+    assert_eq!(
+        pak.get("SB/Content/large.bin", &mut reader).unwrap(),
+        &large[..0x2000]
+    );
+}
+
+#[test]
+fn test_rename_paths() {
+    let bytes = build_pak(
+        repak::Version::V11,
+        &[
+            ("SB/Content/X.txt", false, b"upper"),
+            ("SB/Content/x.txt", false, b"lower"),
+            ("SB/Content/a.txt", false, b"a"),
+        ],
+    );
+    let writer = || {
+        repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap()
+            .into_pakwriter(Cursor::new(bytes.clone()))
+            .unwrap()
+    };
+    let read = |pak_writer: repak::PakWriter<Cursor<Vec<u8>>>| {
+        let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        (pak, reader)
+    };
+
+    // renaming either of two case-only duplicates leaves the other reachable case-insensitively
+    for (renamed, data) in [
+        ("SB/Content/x.txt", b"upper"),
+        ("SB/Content/X.txt", b"lower"),
+    ] {
+        let mut pak_writer = writer();
+        pak_writer
+            .rename_entry(renamed, "SB/Content/y.txt")
+            .unwrap();
+        pak_writer
+            .rename_entry("sb/content/X.TXT", "SB/Content/z.txt")
+            .unwrap();
+        let (pak, mut reader) = read(pak_writer);
+        assert_eq!(pak.get("SB/Content/z.txt", &mut reader).unwrap(), data);
+    }
+
+    // new paths are normalized and made relative to the mount point
+    let mut pak_writer = writer();
+    pak_writer
+        .rename_entry("SB/Content/a.txt", "../../../SB/Content\\Sub/./b.txt")
+        .unwrap();
+    pak_writer
+        .rename_entry("SB/Content/X.txt", "/SB/Content/c.txt")
+        .unwrap();
+    let (pak, mut reader) = read(pak_writer);
+    assert_eq!(
+        pak.files(),
+        [
+            "SB/Content/Sub/b.txt",
+            "SB/Content/c.txt",
+            "SB/Content/x.txt"
+        ]
+    );
+    assert_eq!(pak.get("SB/Content/Sub/b.txt", &mut reader).unwrap(), b"a");
+
+    let bytes = {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../SB/Content/".to_owned(),
+            None,
+        );
+        pak_writer.write_file("a.txt", false, b"a").unwrap();
+        pak_writer.write_index().unwrap().into_inner()
+    };
+    let mut pak_writer = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap()
+        .into_pakwriter(Cursor::new(bytes.clone()))
+        .unwrap();
+    assert!(pak_writer
+        .rename_entry("a.txt", "../../../SB/Other/b.txt")
+        .is_err());
+    pak_writer
+        .rename_entry("a.txt", "../../../sb/content/Sub/b.txt")
+        .unwrap();
+    let (pak, _) = read(pak_writer);
+    assert_eq!(pak.files(), ["Sub/b.txt"]);
+}
+
+#[test]
+fn test_compact_replaced() {
+    for version in [repak::Version::V3, repak::Version::V8B, repak::Version::V11] {
+        let bytes = build_pak(
+            version,
+            &[
+                ("SB/Content/a.txt", true, b"first entry, compressed"),
+                ("SB/Content/b.txt", true, b"replaced entry"),
+                ("SB/Content/c.txt", false, b"stored"),
+            ],
+        );
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap();
+        let mut pak_writer = pak.into_pakwriter(Cursor::new(bytes)).unwrap();
+        pak_writer
+            .replace_entry("SB/Content/b.txt", b"replacement")
+            .unwrap();
+        let orphaned: u64 = pak_writer.orphaned().iter().map(|r| r.end - r.start).sum();
+        let bytes = pak_writer.write_index().unwrap().into_inner();
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let (compacted, reclaimed) =
+            repak::compact(&pak, &mut reader, Cursor::new(vec![])).unwrap();
+        let compacted = compacted.into_inner();
+        assert_eq!(reclaimed, orphaned);
+        assert_eq!(compacted.len() as u64, bytes.len() as u64 - reclaimed);
+
+        let mut reader = Cursor::new(&compacted);
+        let compacted = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(compacted.path_hash_seed(), pak.path_hash_seed());
+        assert_eq!(compacted.files(), pak.files());
+        for (path, entry) in pak.entries() {
+            let moved = compacted.get_file_entry(path).unwrap();
+            assert_eq!(moved.hash, entry.hash);
+            assert_eq!(moved.compression_slot, entry.compression_slot);
+            assert_eq!(moved.flags, entry.flags);
+        }
+        assert_eq!(
+            compacted.get("SB/Content/a.txt", &mut reader).unwrap(),
+            b"first entry, compressed"
+        );
+        assert_eq!(
+            compacted.get("SB/Content/b.txt", &mut reader).unwrap(),
+            b"replacement"
+        );
+        assert_eq!(
+            compacted.get("SB/Content/c.txt", &mut reader).unwrap(),
+            b"stored"
+        );
+    }
+}
+
+#[test]
+fn test_large_offsets() {
+    // entries start past 4 GiB in a sparse file
+    const START: u64 = 0x1_4000_0000;
+    let data: Vec<u8> = (0..0x30000u32).map(|i| (i % 253) as u8).collect();
+    for version in [repak::Version::V4, repak::Version::V8B, repak::Version::V11] {
+        let path = std::env::temp_dir().join(format!(
+            "repak_large_offsets_{}_{version}.pak",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(START).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+
+        let mut pak_writer = repak::PakBuilder::new()
+            .compression([repak::Compression::Zlib])
+            .writer(file, version, "../../../".to_owned(), Some(0));
+        pak_writer.write_file("a/raw.bin", false, &data).unwrap();
+        pak_writer
+            .write_file("a/compressed.bin", true, &data)
+            .unwrap();
+        let mut file = pak_writer.write_index().unwrap();
+
+        let pak = repak::PakBuilder::new().reader(&mut file).unwrap();
+        for name in ["a/raw.bin", "a/compressed.bin"] {
+            assert!(
+                pak.get_file_entry(name).unwrap().offset >= START,
+                "{version}"
+            );
+            assert_eq!(pak.get(name, &mut file).unwrap(), data, "{version}");
+        }
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_keyfinder() {
+    use base64::{engine::general_purpose, Engine as _};
+    let key: [u8; 32] = general_purpose::STANDARD
+        .decode(AES_KEY)
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    // GetPakEncryptionKey with a decoy key setup before it
+    let mut exe = vec![0xCC; 0x40];
+    let decoy: [u8; 32] = std::array::from_fn(|i| i as u8);
+    for key in [decoy, key] {
+        for (i, word) in key.chunks(4).enumerate() {
+            match i {
+                0 => exe.extend([0xC7, 0x01]),
+                _ => exe.extend([0xC7, 0x41, i as u8 * 4]),
+            }
+            exe.extend(word);
+        }
+        exe.push(0xC3);
+    }
+    assert_eq!(repak::keyfinder::candidates(&exe).len(), 2);
+
+    let mut reader = std::io::BufReader::new(
+        std::fs::File::open("tests/packs/pack_v11_encryptindex.pak").unwrap(),
+    );
+    let found = repak::keyfinder::scan(&exe, &mut reader).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].key, key);
+    assert_eq!(found[0].scheme, repak::encryption::Builtin::UnrealStandard);
+    assert_eq!(found[0].mount_point, "../mount/point/root/");
+
+    let pak = repak::PakBuilder::new()
+        .key(found[0].aes())
+        .encryption(repak::UnrealStandard)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.mount_point(), "../mount/point/root/");
+
+    let mut reader =
+        std::io::BufReader::new(std::fs::File::open("tests/packs/pack_v11_encrypt.pak").unwrap());
+    assert!(repak::keyfinder::scan(&exe, &mut reader).is_err());
+}
+
+#[test]
+fn test_filelist() {
+    use repak::filelist;
+
+    let mut entries = filelist::parse_response(concat!(
+        "\"Cooked/SB/Content/a.uasset\" \"../../../SB/Content/a.uasset\" -compress\n",
+        "\"Cooked/SB/Content/b.uasset\" \"../../../SB/Content/b.uasset\"\n",
+        "\"Cooked/SB/Content/Maps/c.umap\" \"../../../SB/Content/Maps/c.umap\" -compress\n",
+    ))
+    .unwrap();
+    let order = filelist::parse_order(concat!(
+        "\"../../../SB/Content/Maps/c.umap\" 0\n",
+        "\"../../../SB/Content/a.uasset\" 1\n",
+    ))
+    .unwrap();
+    filelist::apply_order(&mut entries, &order);
+
+    let mount_point = filelist::mount_point(&entries);
+    assert_eq!(mount_point, "../../../SB/Content/");
+    let mut pak_writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            mount_point,
+            Some(0x205C5A7D),
+        );
+    filelist::write(&mut pak_writer, &entries, |source| {
+        Ok(source.to_string_lossy().repeat(4).into_bytes())
+    })
+    .unwrap();
+
+    let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut by_offset = pak
+        .entries()
+        .map(|(path, entry)| (entry.offset, path, pak.entry_compression(entry)))
+        .collect::<Vec<_>>();
+    by_offset.sort_by_key(|(offset, _, _)| *offset);
+    assert_eq!(
+        by_offset
+            .into_iter()
+            .map(|(_, path, compression)| (path, compression))
+            .collect::<Vec<_>>(),
+        vec![
+            ("Maps/c.umap", Some(repak::Compression::Zlib)),
+            ("a.uasset", Some(repak::Compression::Zlib)),
+            ("b.uasset", None),
+        ]
+    );
+    assert_eq!(
+        pak.get("b.uasset", &mut reader).unwrap(),
+        "Cooked/SB/Content/b.uasset".repeat(4).into_bytes()
+    );
+
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../SB/Config/".to_owned(),
+        None,
+    );
+    assert!(matches!(
+        filelist::write(&mut pak_writer, &entries, |_| Ok(vec![])),
+        Err(repak::Error::PrefixMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_layout() {
+    use repak::RegionKind;
+
+    let fixtures: [(repak::Version, &[u8]); 3] = [
+        (
+            repak::Version::V5,
+            include_bytes!("packs/pack_v5_compress.pak"),
+        ),
+        (repak::Version::V8B, include_bytes!("packs/pack_v8b.pak")),
+        (
+            repak::Version::V11,
+            include_bytes!("packs/pack_v11_compress.pak"),
+        ),
+    ];
+    for (version, bytes) in fixtures {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let layout = pak.layout(&mut reader).unwrap();
+
+        // regions are contiguous and cover the whole file
+        let mut pos = 0;
+        for region in &layout.regions {
+            assert_eq!(region.offset, pos, "{version}\n{layout}");
+            pos += region.size;
+        }
+        assert_eq!(pos, bytes.len() as u64);
+        assert!(!layout
+            .regions
+            .iter()
+            .any(|region| region.kind == RegionKind::Unknown));
+
+        let count = |kind| layout.regions.iter().filter(|r| r.kind == kind).count();
+        assert_eq!(count(RegionKind::EntryHeader), pak.files().len());
+        assert_eq!(count(RegionKind::EntryData), pak.files().len());
+        assert_eq!(count(RegionKind::Index), 1);
+        assert_eq!(layout.regions.last().unwrap().kind, RegionKind::Footer);
+
+        let phi = version.version_major() >= repak::VersionMajor::PathHashIndex;
+        assert_eq!(count(RegionKind::PathHashIndex), phi as usize);
+        assert_eq!(count(RegionKind::FullDirectoryIndex), phi as usize);
+
+        let blobs = pak.index_blobs(&mut reader).unwrap();
+        let mount_point = pak.mount_point().as_bytes();
+        assert_eq!(&blobs.index[4..4 + mount_point.len()], mount_point);
+        assert_eq!(blobs.path_hash_index.is_some(), phi);
+        assert_eq!(blobs.full_directory_index.is_some(), phi);
+    }
+
+    // padding inserted for alignment is reported as such
+    let mut pak_writer = repak::PakBuilder::new().alignment(0x100).writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    pak_writer.write_file("a.txt", false, b"a").unwrap();
+    pak_writer.write_file("b.txt", false, b"b").unwrap();
+    let mut reader = Cursor::new(pak_writer.write_index().unwrap().into_inner());
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let kinds = pak
+        .layout(&mut reader)
+        .unwrap()
+        .regions
+        .iter()
+        .map(|region| region.kind)
+        .take(5)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            RegionKind::Padding,
+            RegionKind::EntryHeader,
+            RegionKind::EntryData,
+            RegionKind::Padding,
+            RegionKind::EntryHeader,
+        ]
+    );
+}